@@ -0,0 +1,48 @@
+defmodule JupSwap.Quote do
+  @moduledoc """
+  A Jupiter quote as returned by `JupSwap.Native.get_quote/4`. Mints are
+  base58 strings and amounts are integers in atomic units. `warnings` holds
+  the route advisories a swap of the quote would log, e.g. flagged AMMs or
+  low liquidity.
+  """
+
+  defstruct [
+    :input_mint,
+    :output_mint,
+    :in_amount,
+    :out_amount,
+    :other_amount_threshold,
+    :price_impact_pct,
+    :slippage_bps,
+    :swap_mode,
+    :route_summary,
+    labels: [],
+    route_plan: [],
+    warnings: []
+  ]
+end
+
+defmodule JupSwap.RoutePlan do
+  @moduledoc """
+  One leg of a quote's route, carrying `percent` of the input.
+  """
+
+  defstruct [:percent, :swap_info]
+end
+
+defmodule JupSwap.SwapInfo do
+  @moduledoc """
+  The AMM a route leg swaps through and the amounts it moves.
+  """
+
+  defstruct [
+    :amm_key,
+    :label,
+    :input_mint,
+    :output_mint,
+    :in_amount,
+    :out_amount,
+    :fee_amount,
+    :fee_mint
+  ]
+end
@@ -33,7 +33,63 @@ defmodule JupSwap.Native do
     mode: mode,
     force_build: System.get_env("JUP_SWAP_BUILD") in ["1", "true"]
 
-  def quick_swap(_token_to, _token_from, _amount), do: err()
+  def quick_swap(token_to, token_from, amount), do: quick_swap(token_to, token_from, amount, nil, nil)
+
+  def quick_swap(token_to, token_from, amount, only_direct_routes),
+    do: quick_swap(token_to, token_from, amount, only_direct_routes, nil)
+
+  def quick_swap(token_to, token_from, amount, only_direct_routes, slippage_bps),
+    do: quick_swap(token_to, token_from, amount, only_direct_routes, slippage_bps, nil, nil, nil)
+
+  def quick_swap(token_to, token_from, amount, only_direct_routes, slippage_bps, skip_preflight, max_retries),
+    do: quick_swap(token_to, token_from, amount, only_direct_routes, slippage_bps, skip_preflight, max_retries, nil)
+
+  def quick_swap(
+        _token_to,
+        _token_from,
+        _amount,
+        _only_direct_routes,
+        _slippage_bps,
+        _skip_preflight,
+        _max_retries,
+        _min_out_amount
+      ),
+      do: err()
+
+  def quick_swap_with_key(_token_to, _token_from, _amount, _secret_key), do: err()
+  def quick_swap_with_keyfile(_token_to, _token_from, _amount, _keypair_path), do: err()
+  @doc """
+  Starts `quick_swap/3` without blocking the caller and returns a reference.
+  The outcome arrives as a `{:jup_swap_result, reference, outcome}` message.
+  """
+  def quick_swap_async(token_to, token_from, amount) do
+    reference = make_ref()
+    :ok = start_quick_swap(reference, token_to, token_from, amount)
+    reference
+  end
+
+  def start_quick_swap(_reference, _token_to, _token_from, _amount), do: err()
+  def simulate_swap(_token_to, _token_from, _amount, _key_env_var), do: err()
+  def swap_from_quote(_quote_json, _key_env_var), do: err()
+  def build_swap_transaction(_token_to, _token_from, _amount, _owner), do: err()
+  def get_quote(_token_from, _token_to, _amount, _slippage_bps), do: err()
+  def best_quote(_token_from, _token_to, _amount), do: err()
+  def get_price(_input_mint, _output_mint, _ui_amount), do: err()
+  def route_summary(_quote_json), do: err()
+  def route_split(_quote_json), do: err()
+  def known_mint(_symbol), do: err()
+  def round_trip_quote(_token_a, _token_b, _amount), do: err()
+  def benchmark_quote(_input_mint, _output_mint, _amount, _n, _concurrency), do: err()
+  def drain(_timeout_ms), do: err()
+  def resume(), do: err()
+  def rpc_health(), do: err()
+  def jupiter_healthy(), do: err()
+  def runtime_stats(), do: err()
+  def derive_ata(_owner, _mint), do: err()
+  def derive_ata_with_program(_owner, _mint, _token_program), do: err()
+  def to_atomic_amount(_mint, _ui_amount, _decimals), do: err()
+  def to_ui_amount(_mint, _amount, _decimals), do: err()
+  def normalize_sol_mint(_mint, _wrap), do: err()
 
   defp err, do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -0,0 +1,77 @@
+//! A cache of recent blockhashes per RPC endpoint, so building a swap does
+//! not wait on `getLatestBlockhash`
+
+use std::{
+    collections::BTreeMap,
+    sync::{Mutex, Once},
+    time::{Duration, Instant},
+};
+
+use solana_client::{client_error::ClientError, nonblocking::rpc_client::RpcClient};
+use solana_sdk::hash::Hash;
+
+/// How often the background task refreshes the cached blockhash
+const REFRESH_INTERVAL: Duration = Duration::from_secs(20);
+/// A cached blockhash older than this is never used, e.g. when refreshing
+/// keeps failing. Blockhashes expire after roughly 60 seconds.
+const MAX_AGE: Duration = Duration::from_secs(30);
+
+/// Keyed by RPC URL and commitment like `crate::rpc_client`, so a change of
+/// `RPC_URL` or `CLUSTER` never serves a blockhash from the old cluster
+static LATEST: Mutex<BTreeMap<(String, String), (Hash, Instant)>> = Mutex::new(BTreeMap::new());
+static REFRESHER: Once = Once::new();
+
+/// A recent blockhash for building a transaction. Served from a cache kept
+/// fresh by a background task on the runtime, unless `BLOCKHASH_CACHE=false`,
+/// in which case every call asks the RPC.
+pub async fn latest(rpc_client: &RpcClient) -> Result<Hash, ClientError> {
+    if !enabled() {
+        return rpc_client.get_latest_blockhash().await;
+    }
+
+    REFRESHER.call_once(|| {
+        tokio::spawn(refresh());
+    });
+
+    if let Some((blockhash, fetched)) = LATEST.lock().unwrap().get(&key(rpc_client)) {
+        if fetched.elapsed() < MAX_AGE {
+            return Ok(*blockhash);
+        }
+    }
+
+    let blockhash = rpc_client.get_latest_blockhash().await?;
+    store(rpc_client, blockhash);
+
+    Ok(blockhash)
+}
+
+fn enabled() -> bool {
+    std::env::var("BLOCKHASH_CACHE").map(|s| s != "false").unwrap_or(true)
+}
+
+fn key(rpc_client: &RpcClient) -> (String, String) {
+    (rpc_client.url(), rpc_client.commitment().commitment.to_string())
+}
+
+/// Caches `blockhash` for `rpc_client`, dropping entries for endpoints that
+/// are no longer refreshed
+fn store(rpc_client: &RpcClient, blockhash: Hash) {
+    let mut latest = LATEST.lock().unwrap();
+    latest.retain(|_, (_, fetched)| fetched.elapsed() < MAX_AGE);
+    latest.insert(key(rpc_client), (blockhash, Instant::now()));
+}
+
+/// Refreshes the blockhash of the endpoint currently configured
+async fn refresh() {
+    let mut interval = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        interval.tick().await;
+        let Ok(rpc_client) = crate::rpc_client() else {
+            continue;
+        };
+        match rpc_client.get_latest_blockhash().await {
+            Ok(blockhash) => store(&rpc_client, blockhash),
+            Err(e) => tracing::warn!(error = %e, "could not refresh the cached blockhash"),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Swaps currently running, keyed by operation id, with the signature once
+/// the transaction has been handed to the network
+static IN_FLIGHT: Mutex<BTreeMap<String, Option<String>>> = Mutex::new(BTreeMap::new());
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+/// Removes the swap from the in-flight set when it finishes, however it ends
+pub struct InFlight {
+    operation_id: String,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.lock().unwrap().remove(&self.operation_id);
+    }
+}
+
+/// Registers a new swap, or returns `None` once draining has started
+pub fn register(operation_id: &str) -> Option<InFlight> {
+    let mut in_flight = IN_FLIGHT.lock().unwrap();
+    if DRAINING.load(Ordering::SeqCst) {
+        return None;
+    }
+    in_flight.insert(operation_id.to_string(), None);
+
+    Some(InFlight {
+        operation_id: operation_id.to_string(),
+    })
+}
+
+/// Number of swaps currently running
+pub fn count() -> usize {
+    IN_FLIGHT.lock().unwrap().len()
+}
+
+/// Marks the swap as submitted right before it is sent. Returns `false` when
+/// draining has started, in which case the swap must be cancelled instead.
+pub fn submit(operation_id: &str, signature: String) -> bool {
+    let mut in_flight = IN_FLIGHT.lock().unwrap();
+    if DRAINING.load(Ordering::SeqCst) {
+        return false;
+    }
+    in_flight.insert(operation_id.to_string(), Some(signature));
+
+    true
+}
+
+/// Stops accepting new swaps and waits up to `timeout` for submitted ones to
+/// finish. Swaps that have not been submitted yet cancel themselves. Returns
+/// the signatures of the swaps that were already submitted, since those can
+/// no longer be cancelled. New swaps stay refused until `resume`.
+pub fn drain(timeout: Duration) -> Vec<String> {
+    let submitted: Vec<String> = {
+        let in_flight = IN_FLIGHT.lock().unwrap();
+        DRAINING.store(true, Ordering::SeqCst);
+        in_flight.values().flatten().cloned().collect()
+    };
+
+    let started = Instant::now();
+    while started.elapsed() < timeout && !IN_FLIGHT.lock().unwrap().is_empty() {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    submitted
+}
+
+/// Accepts new swaps again after `drain`
+pub fn resume() {
+    DRAINING.store(false, Ordering::SeqCst);
+}
@@ -1,11 +1,15 @@
+//! Client for the Jupiter v6 swap API. It has no rustler dependency, so Rust
+//! code can use it directly as `jup_swap::jup_ag`.
+
 use {
+    itertools::Itertools,
     serde::{Deserialize, Serialize},
     solana_sdk::{
+        instruction::{AccountMeta, Instruction},
         pubkey::{ParsePubkeyError, Pubkey},
         transaction::{VersionedTransaction},
     },
-    reqwest,
-    std::fmt,
+    std::sync::OnceLock,
 };
 
 mod field_as_string;
@@ -29,11 +33,49 @@ pub enum Error {
     #[error("bincode: {0}")]
     Bincode(#[from] bincode::Error),
 
-    #[error("Jupiter API: {0}")]
-    JupiterApi(String),
+    #[error("Jupiter API: {message} ({})", code.as_deref().unwrap_or("no error code"))]
+    JupiterApi {
+        /// Stable machine-readable code, e.g. `TOKEN_NOT_TRADABLE`
+        code: Option<String>,
+        message: String,
+    },
+
+    #[error("no route found")]
+    NoRoute,
+
+    /// `/swap` or `/swap-instructions` refused the quote as too old; fetch a
+    /// fresh one
+    #[error("quote expired: {0}")]
+    QuoteExpired(String),
+
+    #[error("malformed swap instruction: {0}")]
+    MalformedSwapInstruction(String),
 
     #[error("serde_json: {0}")]
     SerdeJson(#[from] serde_json::Error),
+
+    /// Jupiter's own simulation of the swap failed, so sending it would fail
+    /// on-chain too
+    #[error(
+        "swap simulation failed: {error} ({}){}",
+        code.as_deref().unwrap_or("no error code"),
+        slot.map(|slot| format!(" at slot {}", slot)).unwrap_or_default()
+    )]
+    Simulation {
+        code: Option<String>,
+        error: String,
+        slot: Option<u64>,
+    },
+
+    /// Still rate limited (HTTP 429) after `RATE_LIMIT_ATTEMPTS` tries
+    #[error("rate limited by Jupiter (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<std::time::Duration> },
+
+    #[error("invalid {field} in quote: {value:?}")]
+    InvalidNumber { field: &'static str, value: String },
+
+    #[error("invalid JUP_API_KEY: {0}")]
+    InvalidApiKey(#[from] reqwest::header::InvalidHeaderValue),
 }
 
 /// Generic response with timing information
@@ -51,7 +93,7 @@ pub struct Price {
     pub id: Pubkey,
     pub mint_symbol: String,
     #[serde(with = "field_as_string")]
-    pub vs_token: String,
+    pub vs_token: Pubkey,
     pub vs_token_symbol: String,
     pub price: f64,
 }
@@ -69,7 +111,103 @@ pub struct Quote {
     pub price_impact_pct: String,
     pub route_plan: Vec<RoutePlan>,
     pub other_amount_threshold: String,
-    pub swap_mode: String,
+    pub swap_mode: SwapMode,
+    #[serde(default)]
+    pub context_slot: Option<u64>,
+    #[serde(default)]
+    pub time_taken: f64,
+    /// Referral fee reserved by the quote, see `QuoteConfig::platform_fee_bps`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform_fee: Option<PlatformFee>,
+}
+
+impl Quote {
+    /// `price_impact_pct` as a number
+    pub fn price_impact(&self) -> Result<f64> {
+        parse_number("priceImpactPct", &self.price_impact_pct)
+    }
+
+    /// `in_amount` in atomic units of the input mint
+    pub fn in_amount_u64(&self) -> Result<u64> {
+        parse_number("inAmount", &self.in_amount)
+    }
+
+    /// `out_amount` in atomic units of the output mint
+    pub fn out_amount_u64(&self) -> Result<u64> {
+        parse_number("outAmount", &self.out_amount)
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(field: &'static str, value: &str) -> Result<T> {
+    value.parse().map_err(|_| Error::InvalidNumber {
+        field,
+        value: value.to_string(),
+    })
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformFee {
+    pub amount: String,
+    pub fee_bps: u64,
+}
+
+/// Which side of the swap the quoted amount fixes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SwapMode {
+    /// The amount is the input; the output floats
+    #[default]
+    ExactIn,
+    /// The amount is the output; the input floats
+    ExactOut,
+}
+
+impl std::fmt::Display for SwapMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SwapMode::ExactIn => write!(f, "ExactIn"),
+            SwapMode::ExactOut => write!(f, "ExactOut"),
+        }
+    }
+}
+
+impl std::str::FromStr for SwapMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ExactIn" => Ok(SwapMode::ExactIn),
+            "ExactOut" => Ok(SwapMode::ExactOut),
+            _ => Err(format!("unknown swap mode {}, expected ExactIn or ExactOut", s)),
+        }
+    }
+}
+
+/// How the slippage tolerance of a quote was chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlippageMode {
+    /// A fixed `slippageBps` was requested
+    Fixed,
+    /// Jupiter picked the slippage
+    Auto,
+}
+
+/// Freshness, timing and slippage information about a quote
+#[derive(Clone, Debug)]
+pub struct QuoteMetadata {
+    pub context_slot: Option<u64>,
+    pub time_taken: f64,
+    pub slippage_mode: SlippageMode,
+}
+
+impl QuoteMetadata {
+    pub fn new(quote: &Quote, slippage_mode: SlippageMode) -> Self {
+        QuoteMetadata {
+            context_slot: quote.context_slot,
+            time_taken: quote.time_taken,
+            slippage_mode,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -128,25 +266,283 @@ pub struct Swap {
     //pub setup: Option<Transaction>,
     pub swap: VersionedTransaction,
     //pub cleanup: Option<Transaction>,
+    pub prioritization_fee_lamports: u64,
+    pub dynamic_slippage_report: Option<DynamicSlippageReport>,
 }
 
+/// What Jupiter settled on when `dynamicSlippage` is requested
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicSlippageReport {
+    /// The slippage the swap was built with
+    pub slippage_bps: u64,
+    #[serde(default)]
+    pub other_amount: Option<u64>,
+    /// Negative when the simulated price was better than quoted
+    #[serde(default)]
+    pub simulated_incurred_slippage_bps: Option<i64>,
+    #[serde(default)]
+    pub amplification_ratio: Option<String>,
+    #[serde(default)]
+    pub category_name: Option<String>,
+    #[serde(default)]
+    pub heuristic_max_slippage_bps: Option<u64>,
+}
+
+/// Instructions required to execute a swap, for callers assembling the
+/// transaction themselves
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SwapInstructions {
+    #[serde(default)]
+    pub compute_budget_instructions: Vec<JupiterInstruction>,
+    #[serde(default)]
+    pub setup_instructions: Vec<JupiterInstruction>,
+    /// Present with `useTokenLedger`; must run before the setup instructions
+    #[serde(default)]
+    pub token_ledger_instruction: Option<JupiterInstruction>,
+    pub swap_instruction: JupiterInstruction,
+    /// Extra instructions some routes need; they run after the swap and
+    /// before cleanup
+    #[serde(default)]
+    pub other_instructions: Vec<JupiterInstruction>,
+    pub cleanup_instruction: Option<JupiterInstruction>,
+    #[serde(default)]
+    pub address_lookup_table_addresses: Vec<String>,
+    #[serde(default)]
+    pub prioritization_fee_lamports: u64,
+    #[serde(default)]
+    pub prioritization_type: Option<PrioritizationType>,
+    /// CU limit Jupiter simulated the swap at
+    #[serde(default)]
+    pub compute_unit_limit: Option<u32>,
+    #[serde(default)]
+    pub simulation_error: Option<SimulationError>,
+    #[serde(default)]
+    pub simulation_slot: Option<u64>,
+    #[serde(default)]
+    pub dynamic_slippage_report: Option<DynamicSlippageReport>,
+}
+
+/// How Jupiter priced the swap's priority fee
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrioritizationType {
+    #[serde(default)]
+    pub compute_budget: Option<ComputeBudgetPrioritization>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComputeBudgetPrioritization {
+    /// The CU price in Jupiter's compute budget instructions
+    #[serde(default)]
+    pub micro_lamports: u64,
+    /// Jupiter's recommended CU price for current network conditions
+    #[serde(default)]
+    pub estimated_micro_lamports: Option<u64>,
+}
+
+/// Why Jupiter's simulation of a swap failed
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationError {
+    #[serde(default)]
+    pub error_code: Option<String>,
+    pub error: String,
+}
+
+impl SimulationError {
+    fn into_error(self, slot: Option<u64>) -> Error {
+        Error::Simulation {
+            code: self.error_code,
+            error: self.error,
+            slot,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JupiterInstruction {
+    #[serde(with = "field_as_string")]
+    pub program_id: Pubkey,
+    #[serde(default)]
+    pub accounts: Vec<JupiterAccount>,
+    #[serde(default)]
+    pub data: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JupiterAccount {
+    #[serde(with = "field_as_string")]
+    pub pubkey: Pubkey,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+impl SwapInstructions {
+    /// Rejects a swap instruction that would decode into a bogus instruction,
+    /// as seen occasionally on corrupted API responses
+    pub fn validate_swap_instruction(&self) -> Result<()> {
+        let swap_instruction = &self.swap_instruction;
+        let problem = if swap_instruction.program_id == Pubkey::default() {
+            "missing program id"
+        } else if swap_instruction.accounts.is_empty() {
+            "missing accounts"
+        } else if swap_instruction.data.is_empty() {
+            "empty data"
+        } else {
+            return Ok(());
+        };
+
+        Err(Error::MalformedSwapInstruction(problem.to_string()))
+    }
+}
+
+impl JupiterInstruction {
+    pub fn into_instruction(self) -> Result<Instruction> {
+        Ok(Instruction {
+            program_id: self.program_id,
+            accounts: self
+                .accounts
+                .into_iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.pubkey,
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: base64::decode(self.data)?,
+        })
+    }
+}
+
+/// `errorCode`s with which the swap endpoints reject a quote past Jupiter's
+/// staleness window
+const QUOTE_EXPIRED_CODES: &[&str] = &["QUOTE_EXPIRED", "STALE_QUOTE"];
 
 pub fn maybe_jupiter_api_error<T>(value: serde_json::Value) -> Result<T>
 where
     T: serde::de::DeserializeOwned,
 {
     #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
     struct ErrorResponse {
         error: String,
+        error_code: Option<String>,
     }
-    if let Ok(ErrorResponse { error }) = serde_json::from_value::<ErrorResponse>(value.clone()) {
-        println!("{error:#?}");
-        Err(Error::JupiterApi(error))
+    if let Ok(ErrorResponse { error, error_code }) = serde_json::from_value::<ErrorResponse>(value.clone()) {
+        tracing::error!(error, ?error_code, "Jupiter API error");
+        // Codes with a dedicated variant; everything else keeps the raw code
+        Err(match error_code.as_deref() {
+            Some("COULD_NOT_FIND_ANY_ROUTE" | "NO_ROUTES_FOUND") => Error::NoRoute,
+            Some("RATE_LIMITED") => Error::RateLimited { retry_after: None },
+            Some(code) if QUOTE_EXPIRED_CODES.contains(&code) => Error::QuoteExpired(error),
+            _ => Error::JupiterApi {
+                code: error_code,
+                message: error,
+            },
+        })
     } else {
         serde_json::from_value(value).map_err(|err| err.into())
     }
 }
 
+const DEFAULT_API_BASE: &str = "https://quote-api.jup.ag/v6";
+
+/// Jupiter API base URL, `JUP_API_BASE` for self-hosted or alternative hosts
+/// such as `https://lite-api.jup.ag/swap/v1`
+pub fn api_base() -> String {
+    std::env::var("JUP_API_BASE")
+        .map(|base| base.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| DEFAULT_API_BASE.to_string())
+}
+
+const DEFAULT_HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// `JUP_HTTP_TIMEOUT_SECS`, the connect and whole-request timeout for Jupiter
+/// calls, so a hung connection cannot hold a scheduler forever
+fn http_timeout() -> std::time::Duration {
+    match std::env::var("JUP_HTTP_TIMEOUT_SECS") {
+        Ok(s) => match s.parse::<u64>() {
+            Ok(secs) if secs > 0 => std::time::Duration::from_secs(secs),
+            _ => {
+                tracing::warn!(value = %s, "invalid JUP_HTTP_TIMEOUT_SECS, using the default");
+                DEFAULT_HTTP_TIMEOUT
+            }
+        },
+        Err(_) => DEFAULT_HTTP_TIMEOUT,
+    }
+}
+
+/// One HTTP client for every Jupiter request, so connections and TLS
+/// sessions are reused. `JUP_API_KEY` and `JUP_HTTP_TIMEOUT_SECS`, read when
+/// the client is first built, apply to every request; the key is sent as
+/// `x-api-key` for Jupiter's paid tier.
+pub fn http_client() -> Result<&'static reqwest::Client> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+    if let Some(client) = CLIENT.get() {
+        return Ok(client);
+    }
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Ok(api_key) = std::env::var("JUP_API_KEY") {
+        let mut api_key = reqwest::header::HeaderValue::from_str(api_key.trim())?;
+        api_key.set_sensitive(true);
+        headers.insert("x-api-key", api_key);
+    }
+    let timeout = http_timeout();
+    let client = reqwest::Client::builder()
+        .default_headers(headers)
+        .timeout(timeout)
+        .connect_timeout(timeout)
+        .build()?;
+
+    Ok(CLIENT.get_or_init(|| client))
+}
+
+const RATE_LIMIT_ATTEMPTS: u32 = 3;
+const RATE_LIMIT_INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
+/// Never sleep longer than this for one 429, whatever `Retry-After` asks
+const RATE_LIMIT_MAX_WAIT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Sends the request, sleeping and retrying on HTTP 429. Waits for
+/// `Retry-After` (in seconds) when Jupiter sends it, with exponential backoff
+/// otherwise.
+async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+    let mut backoff = RATE_LIMIT_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        // Bodies here are always in memory, so the request can be cloned for
+        // every attempt
+        let Some(this_attempt) = request.try_clone() else {
+            return Ok(request.send().await?);
+        };
+        let response = this_attempt.send().await?;
+        if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Ok(response);
+        }
+
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(std::time::Duration::from_secs);
+        if attempt >= RATE_LIMIT_ATTEMPTS {
+            return Err(Error::RateLimited { retry_after });
+        }
+
+        let wait = retry_after.unwrap_or(backoff).min(RATE_LIMIT_MAX_WAIT);
+        tracing::warn!(attempt, ?wait, "rate limited by Jupiter, retrying");
+        tokio::time::sleep(wait).await;
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
 /// Get simple price for a given input mint, output mint and amount
 pub async fn price(
     input_mint: Pubkey,
@@ -154,14 +550,15 @@ pub async fn price(
     ui_amount: f64,
 ) -> Result<Response<Price>> {
     let url = format!(
-        "https://quote-api.jup.ag/v6/price?id={}&vsToken={}&amount={}",
+        "{}/price?id={}&vsToken={}&amount={}",
+        api_base(),
         input_mint, output_mint, ui_amount
     );
-    //println!("{}", url);
-    maybe_jupiter_api_error(reqwest::get(url).await?.json().await?)
+    maybe_jupiter_api_error(send(http_client()?.get(url)).await?.json().await?)
 }
 
 /// Get quote for a given input mint, output mint and amount
+#[allow(clippy::too_many_arguments)]
 pub async fn quote(
     input_mint: Pubkey,
     output_mint: Pubkey,
@@ -169,24 +566,120 @@ pub async fn quote(
     only_direct_routes: bool,
     slippage: Option<f64>,
     fees_bps: Option<f64>,
-    swap_mode: String,
+    swap_mode: SwapMode,
+    quote_config: QuoteConfig,
 ) -> Result<Response<Vec<Quote>>> {
-    let url = format!(
-        "https://quote-api.jup.ag/v6/quote?excludeDexes=Phoenix&inputMint={}&outputMint={}&amount={}&onlyDirectRoutes={}&swapMode={}&{}{}",
+    let url = legacy_quote_url(
         input_mint,
         output_mint,
         amount,
         only_direct_routes,
+        slippage,
+        fees_bps,
         swap_mode,
+        quote_config,
+    );
+
+    maybe_jupiter_api_error(no_route_aware_json(send(http_client()?.get(url)).await?).await?)
+}
+
+/// The URL `quote` requests: `quote_url_with_config` with the legacy
+/// percentage `slippage` and `feesBps` parameters, so both quote paths share
+/// the same `QuoteConfig` options
+#[allow(clippy::too_many_arguments)]
+pub fn legacy_quote_url(
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount: u64,
+    only_direct_routes: bool,
+    slippage: Option<f64>,
+    fees_bps: Option<f64>,
+    swap_mode: SwapMode,
+    quote_config: QuoteConfig,
+) -> String {
+    format!(
+        "{}{}{}",
+        quote_url_with_config(
+            input_mint,
+            output_mint,
+            amount.to_string(),
+            only_direct_routes,
+            None,
+            swap_mode,
+            quote_config,
+        ),
         slippage
             .map(|slippage| format!("&slippage={}", slippage))
             .unwrap_or_default(),
         fees_bps
             .map(|fees_bps| format!("&feesBps={}", fees_bps))
             .unwrap_or_default(),
-    );
+    )
+}
+
+/// Fetch the quote at a URL built with `quote_url`
+pub async fn get_quote(client: &reqwest::Client, url: String) -> Result<Quote> {
+    quote_from_response(send(client.get(url)).await?).await
+}
+
+/// Parse a quote-api response into a `Quote`, returning `Error::NoRoute` when
+/// Jupiter has no route rather than a generic API error
+pub async fn quote_from_response(response: reqwest::Response) -> Result<Quote> {
+    maybe_jupiter_api_error(no_route_aware_json(response).await?)
+}
 
-    maybe_jupiter_api_error(reqwest::get(url).await?.json().await?)
+/// Jupiter reports a missing route either as a 404 or as a null/empty `data`
+async fn no_route_aware_json(response: reqwest::Response) -> Result<serde_json::Value> {
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::NoRoute);
+    }
+
+    let value: serde_json::Value = response.json().await?;
+    let empty = match &value {
+        serde_json::Value::Null => true,
+        serde_json::Value::Array(quotes) => quotes.is_empty(),
+        serde_json::Value::Object(fields) => fields
+            .get("data")
+            .is_some_and(|data| data.is_null() || data.as_array().is_some_and(Vec::is_empty)),
+        _ => false,
+    };
+
+    if empty {
+        Err(Error::NoRoute)
+    } else {
+        Ok(value)
+    }
+}
+
+/// Optional quote constraints
+#[derive(Clone, Default)]
+pub struct QuoteConfig {
+    /// Only route through these DEXes (Jupiter labels, e.g. "Orca V2")
+    pub dexes: Option<Vec<String>>,
+    /// Never route through these DEXes. Jupiter rejects it together with
+    /// `dexes`.
+    pub exclude_dexes: Option<Vec<String>>,
+    /// Advanced/experimental: extra query parameters passed through to the
+    /// quote endpoint untouched, for routing knobs (e.g. AMM/CLMM preferences)
+    /// the crate does not model yet. Jupiter's defaults apply when empty.
+    pub extra_params: Vec<(String, String)>,
+    /// Referral fee to reserve in the quote; the swap needs a matching
+    /// `SwapConfig::fee_account` to collect it
+    pub platform_fee_bps: Option<u64>,
+    /// Only consider routes that fit in this many accounts, so large routes
+    /// still fit in a transaction
+    pub max_accounts: Option<u64>,
+    /// `true` keeps routes on liquid intermediate tokens, `false` lets them hop
+    /// through any; `None` leaves it to Jupiter
+    pub restrict_intermediate_tokens: Option<bool>,
+    /// Cap on the slippage Jupiter picks when no `slippageBps` is given
+    pub max_auto_slippage_bps: Option<u64>,
+    /// Trade value in USD above which Jupiter's automatic slippage assumes
+    /// more competition for the route
+    pub auto_slippage_collision_usd_value: Option<u64>,
+    /// Only consider routes that fit in a legacy transaction, to match
+    /// `SwapConfig::as_legacy_transaction`
+    pub as_legacy_transaction: Option<bool>,
 }
 
 pub fn quote_url(
@@ -195,29 +688,153 @@ pub fn quote_url(
     amount: String,
     only_direct_routes: bool,
     slippage: Option<u64>,
-    swap_mode: String,
+    swap_mode: SwapMode,
 ) -> std::string::String {
-    format!(
-        "https://quote-api.jup.ag/v6/quote?inputMint={}&outputMint={}&amount={}&onlyDirectRoutes={}&swapMode={}{}",
+    quote_url_with_config(
         input_mint,
         output_mint,
         amount,
         only_direct_routes,
+        slippage,
         swap_mode,
-        slippage
-            .map(|slippage| format!("&slippageBps={}", slippage))
-            .unwrap_or_default(),
-        //fees_bps
-            //.map(|fees_bps| format!("&feesBps={}", fees_bps))
-            //.unwrap_or_default(),
+        QuoteConfig::default(),
     )
 }
 
-#[derive(Default)]
+pub fn quote_url_with_config(
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount: String,
+    only_direct_routes: bool,
+    slippage: Option<u64>,
+    swap_mode: SwapMode,
+    quote_config: QuoteConfig,
+) -> std::string::String {
+    let mut params = vec![
+        ("inputMint", input_mint.to_string()),
+        ("outputMint", output_mint.to_string()),
+        ("amount", amount),
+        ("onlyDirectRoutes", only_direct_routes.to_string()),
+        ("swapMode", swap_mode.to_string()),
+    ];
+    params.extend(slippage.map(|slippage| ("slippageBps", slippage.to_string())));
+    params.extend(auto_slippage_params(slippage, &quote_config));
+    params.extend(
+        quote_config
+            .platform_fee_bps
+            .map(|platform_fee_bps| ("platformFeeBps", platform_fee_bps.to_string())),
+    );
+    params.extend(
+        quote_config
+            .max_accounts
+            .map(|max_accounts| ("maxAccounts", max_accounts.to_string())),
+    );
+    params.extend(
+        quote_config
+            .restrict_intermediate_tokens
+            .map(|restrict| ("restrictIntermediateTokens", restrict.to_string())),
+    );
+    params.extend(
+        quote_config
+            .as_legacy_transaction
+            .map(|as_legacy_transaction| ("asLegacyTransaction", as_legacy_transaction.to_string())),
+    );
+    params.extend(quote_config.dexes.map(|dexes| ("dexes", dexes.join(","))));
+    params.extend(
+        quote_config
+            .exclude_dexes
+            .map(|exclude_dexes| ("excludeDexes", exclude_dexes.join(","))),
+    );
+
+    let base = format!("{}/quote", api_base());
+    // An unparseable `JUP_API_BASE` fails the request itself, with reqwest's
+    // error for the same URL
+    reqwest::Url::parse_with_params(
+        &base,
+        params
+            .iter()
+            .map(|(key, value)| (*key, value.as_str()))
+            .chain(quote_config.extra_params.iter().map(|(key, value)| (key.as_str(), value.as_str()))),
+    )
+    .map(String::from)
+    .unwrap_or(base)
+}
+
+/// Overrides for Jupiter's automatic slippage, which only applies when no
+/// `slippageBps` is given. Empty when neither override is set.
+fn auto_slippage_params(slippage: Option<u64>, quote_config: &QuoteConfig) -> Vec<(&'static str, String)> {
+    let (max_bps, collision_usd_value) = (
+        quote_config.max_auto_slippage_bps,
+        quote_config.auto_slippage_collision_usd_value,
+    );
+    if slippage.is_some() || (max_bps.is_none() && collision_usd_value.is_none()) {
+        return Vec::new();
+    }
+
+    let mut params = vec![("autoSlippage", true.to_string())];
+    params.extend(max_bps.map(|max_bps| ("maxAutoSlippageBps", max_bps.to_string())));
+    params.extend(collision_usd_value.map(|usd_value| ("autoSlippageCollisionUsdValue", usd_value.to_string())));
+    params
+}
+
+#[derive(Clone, Default)]
 pub struct SwapConfig {
     pub wrap_and_unwrap_sol: Option<bool>,
     pub fee_account: Option<Pubkey>,
-    pub token_ledger: Option<Pubkey>
+    /// Swap the balance change recorded by the token ledger instruction
+    /// instead of the quoted input amount
+    pub use_token_ledger: Option<bool>,
+    /// Token account to receive the output instead of the user's ATA
+    pub destination_token_account: Option<Pubkey>,
+    /// Priority fee bid, Jupiter's default when `None`
+    pub priority_fee_lamports: Option<PrioritizationFeeLamports>,
+    /// Let Jupiter pick the slippage from a simulation, up to `max_bps`
+    pub dynamic_slippage: Option<DynamicSlippage>,
+    /// Build a legacy transaction instead of v0, for signers that cannot
+    /// handle versioned transactions. The quote must be requested with
+    /// `QuoteConfig::as_legacy_transaction` too.
+    pub as_legacy_transaction: Option<bool>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DynamicSlippage {
+    pub max_bps: u64,
+}
+
+/// Lets Jupiter size the priority fee for `priority_level`, up to `max_lamports`
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrioritizationFeeLamports {
+    pub priority_level_with_max_lamports: PriorityLevelWithMaxLamports,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriorityLevelWithMaxLamports {
+    pub priority_level: PriorityLevel,
+    pub max_lamports: u64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PriorityLevel {
+    Medium,
+    High,
+    VeryHigh,
+}
+
+impl std::str::FromStr for PriorityLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "medium" => Ok(PriorityLevel::Medium),
+            "high" => Ok(PriorityLevel::High),
+            "veryHigh" => Ok(PriorityLevel::VeryHigh),
+            _ => Err(format!("unknown priority level {}, expected medium, high or veryHigh", s)),
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -227,8 +844,18 @@ struct SwapRequest {
     #[serde(with = "field_as_string")]
     user_public_key: Pubkey,
     wrap_and_unwrap_sol: Option<bool>,
-    //use_token_ledger: Option<String>,
-    //fee_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    destination_token_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prioritization_fee_lamports: Option<PrioritizationFeeLamports>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fee_account: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    use_token_ledger: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dynamic_slippage: Option<DynamicSlippage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    as_legacy_transaction: Option<bool>,
     quote_response: Quote,
 }
 
@@ -238,6 +865,14 @@ struct SwapResponse {
     //setup_transaction: Option<String>,
     swap_transaction: String,
     //cleanup_transaction: Option<String>,
+    #[serde(default)]
+    prioritization_fee_lamports: u64,
+    #[serde(default)]
+    simulation_error: Option<SimulationError>,
+    #[serde(default)]
+    simulation_slot: Option<u64>,
+    #[serde(default)]
+    dynamic_slippage_report: Option<DynamicSlippageReport>,
 }
 
 
@@ -247,32 +882,181 @@ pub async fn swap_with_config(
     user_public_key: Pubkey,
     swap_config: SwapConfig,
 ) -> Result<Swap> {
-    let url = "https://quote-api.jup.ag/v6/swap";
+    let url = format!("{}/swap", api_base());
 
     let request = SwapRequest {
         quote_response,
         wrap_and_unwrap_sol: swap_config.wrap_and_unwrap_sol,
+        destination_token_account: swap_config.destination_token_account.map(|account| account.to_string()),
+        prioritization_fee_lamports: swap_config.priority_fee_lamports,
+        fee_account: swap_config.fee_account.map(|account| account.to_string()),
+        use_token_ledger: swap_config.use_token_ledger,
+        dynamic_slippage: swap_config.dynamic_slippage,
+        as_legacy_transaction: swap_config.as_legacy_transaction,
         user_public_key,
     };
 
-    let client = reqwest::Client::new();
-    let response = client.post(url)
-        .json(&request)
-        .send()
-        .await?;
+    let response = send(http_client()?.post(url).json(&request)).await?;
     let swap_response = maybe_jupiter_api_error::<SwapResponse>(response.json().await?)?;
+    if let Some(simulation_error) = swap_response.simulation_error {
+        return Err(simulation_error.into_error(swap_response.simulation_slot));
+    }
 
     Ok(Swap {
         swap: decode(swap_response.swap_transaction)?,
+        prioritization_fee_lamports: swap_response.prioritization_fee_lamports,
+        dynamic_slippage_report: swap_response.dynamic_slippage_report,
     })
 }
 
+/// Get the instructions for a swap, to be assembled into a transaction by the caller
+pub async fn swap_with_instructions(
+    quote_response: Quote,
+    user_public_key: Pubkey,
+    swap_config: SwapConfig,
+) -> Result<SwapInstructions> {
+    let url = format!("{}/swap-instructions", api_base());
+
+    let request = SwapRequest {
+        quote_response,
+        wrap_and_unwrap_sol: swap_config.wrap_and_unwrap_sol,
+        destination_token_account: swap_config.destination_token_account.map(|account| account.to_string()),
+        prioritization_fee_lamports: swap_config.priority_fee_lamports,
+        fee_account: swap_config.fee_account.map(|account| account.to_string()),
+        use_token_ledger: swap_config.use_token_ledger,
+        dynamic_slippage: swap_config.dynamic_slippage,
+        as_legacy_transaction: swap_config.as_legacy_transaction,
+        user_public_key,
+    };
+
+    let response = send(http_client()?.post(url).json(&request)).await?;
+    let mut swap_instructions = maybe_jupiter_api_error::<SwapInstructions>(response.json().await?)?;
+    if let Some(simulation_error) = swap_instructions.simulation_error.take() {
+        return Err(simulation_error.into_error(swap_instructions.simulation_slot));
+    }
+
+    Ok(swap_instructions)
+}
+
 /// Get swap serialized transactions for a quote using `SwapConfig` defaults
 pub async fn swap(route: Quote, user_public_key: Pubkey) -> Result<Swap> {
     swap_with_config(route, user_public_key, SwapConfig::default()).await
 }
 
 
+/// Groups the route plan into hops. Consecutive legs that share an input mint
+/// are parallel splits of the same hop.
+pub fn route_hops(quote: &Quote) -> Vec<Vec<&RoutePlan>> {
+    let mut hops: Vec<Vec<&RoutePlan>> = Vec::new();
+    for leg in &quote.route_plan {
+        match hops.last_mut() {
+            Some(hop) if hop[0].swap_info.input_mint == leg.swap_info.input_mint => hop.push(leg),
+            _ => hops.push(vec![leg]),
+        }
+    }
+    hops
+}
+
+/// Renders the route plan as a one-line path, e.g. `Orca(80%) + Meteora(20%) → Raydium`
+pub fn route_summary(quote: &Quote) -> String {
+    route_hops(quote)
+        .iter()
+        .map(|hop| match hop.as_slice() {
+            [leg] => leg.swap_info.label.clone(),
+            legs => legs
+                .iter()
+                .map(|leg| format!("{}({}%)", leg.swap_info.label, leg.percent))
+                .join(" + "),
+        })
+        .join(" → ")
+}
+
+/// `(label, percent)` for every leg of the route plan. The percent is the
+/// share of its hop's input, so a multi-hop route has legs at 100% each.
+pub fn route_split(quote: &Quote) -> Vec<(String, u64)> {
+    quote
+        .route_plan
+        .iter()
+        .map(|leg| (leg.swap_info.label.clone(), leg.percent))
+        .collect()
+}
+
+/// Amount of each intermediate mint the route passes through, summed across the
+/// parallel branches of the hop that produces it
+pub fn intermediate_amounts(quote: &Quote) -> Vec<(Pubkey, u64)> {
+    let hops = route_hops(quote);
+    hops.iter()
+        .take(hops.len().saturating_sub(1))
+        .map(|hop| {
+            let amount = hop
+                .iter()
+                .map(|leg| leg.swap_info.out_amount.parse::<u64>().unwrap_or_default())
+                .sum();
+            (hop[0].swap_info.output_mint, amount)
+        })
+        .collect()
+}
+
+/// The distinct DEX labels a quote routes through, used to pin a re-quote to
+/// the original route via `QuoteConfig::dexes`
+pub fn route_dexes(quote: &Quote) -> Vec<String> {
+    quote
+        .route_plan
+        .iter()
+        .map(|leg| leg.swap_info.label.clone())
+        .unique()
+        .collect()
+}
+
+/// Whether two quotes go through the same AMMs in the same order
+pub fn same_route(a: &Quote, b: &Quote) -> bool {
+    a.route_plan
+        .iter()
+        .map(|leg| leg.swap_info.amm_key)
+        .eq(b.route_plan.iter().map(|leg| leg.swap_info.amm_key))
+}
+
+/// Advisories for legs that go through a flagged AMM, matched by label or
+/// AMM key. They are informational and never fail a swap.
+pub fn route_warnings(quote: &Quote, flagged_amms: &[String]) -> Vec<String> {
+    quote
+        .route_plan
+        .iter()
+        .filter(|leg| {
+            flagged_amms
+                .iter()
+                .any(|flagged| *flagged == leg.swap_info.label || *flagged == leg.swap_info.amm_key.to_string())
+        })
+        .map(|leg| format!("route uses flagged AMM {} ({})", leg.swap_info.label, leg.swap_info.amm_key))
+        .unique()
+        .collect()
+}
+
+/// Low-liquidity advisories for a v6 quote, which has no per-market
+/// liquidity flag: legs that pay out nothing, and an overall price impact at
+/// or above `price_impact_threshold` (in the units of `priceImpactPct`).
+/// They are informational and never fail a swap.
+pub fn liquidity_warnings(quote: &Quote, price_impact_threshold: Option<f64>) -> Vec<String> {
+    let mut warnings: Vec<String> = quote
+        .route_plan
+        .iter()
+        .filter(|leg| leg.swap_info.out_amount.parse::<u64>().is_ok_and(|out_amount| out_amount == 0))
+        .map(|leg| format!("not enough liquidity in {} ({})", leg.swap_info.label, leg.swap_info.amm_key))
+        .unique()
+        .collect();
+
+    if let (Some(threshold), Ok(price_impact)) = (price_impact_threshold, quote.price_impact()) {
+        if price_impact >= threshold {
+            warnings.push(format!(
+                "low liquidity: price impact {} is at or above {}",
+                price_impact, threshold
+            ));
+        }
+    }
+
+    warnings
+}
+
 fn decode(base64_transaction: String) -> Result<VersionedTransaction> {
     bincode::deserialize(&base64::decode(base64_transaction)?).map_err(|err| err.into())
 }
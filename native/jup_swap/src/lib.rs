@@ -1,17 +1,38 @@
 use {
-    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_client::{
+        client_error::{ClientError, ClientErrorKind},
+        nonblocking::rpc_client::RpcClient,
+        rpc_config::RpcSendTransactionConfig,
+    },
     solana_sdk::{
+        address_lookup_table::{state::AddressLookupTable, AddressLookupTableAccount},
         bs58,
         commitment_config::CommitmentConfig,
+        compute_budget::ComputeBudgetInstruction,
+        hash::Hash,
+        instruction::{Instruction, InstructionError},
+        message::{v0, Message, VersionedMessage},
+        packet::PACKET_DATA_SIZE,
+        program_pack::Pack,
         pubkey::Pubkey,
-        signature::{read_keypair_file, Keypair, Signer},
-        transaction::VersionedTransaction,
+        signature::{read_keypair_file, Keypair, Signature, Signer},
+        transaction::{TransactionError, VersionedTransaction},
     },
 };
+use serde::Serialize;
 use thiserror::Error;
-use rustler::{Encoder, Env, Term};
-use tokio::runtime::{Runtime, Handle};
-use std::sync::Once;
+use rustler::{Atom, Encoder, Env, OwnedEnv, Term};
+use tokio::runtime::Runtime;
+use futures::StreamExt;
+use governor::{
+    clock::DefaultClock,
+    state::{InMemoryState, NotKeyed},
+    Quota, RateLimiter,
+};
+use std::num::NonZeroU32;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
 
 // Remove this line as it's unused
 // use futures::executor::block_on;
@@ -19,6 +40,39 @@ use std::sync::Once;
 rustler::atoms! {
     swap,
     unknown,
+    no_route,
+    route_unavailable,
+    invalid_amount,
+    quote_failed,
+    send_failed,
+    output_too_small,
+    input_too_large,
+    price_impact_too_high,
+    malformed_swap_instruction,
+    send_timeout,
+    fee_too_high,
+    draining,
+    cancelled,
+    jupiter_api,
+    quote_expired,
+    rate_limited,
+    unsupported_cluster,
+    keypair_file,
+    invalid_keypair,
+    confirmed,
+    finalized,
+    dropped,
+    submitted,
+    simulated,
+    rejected,
+    failed,
+    ok,
+    jup_swap_result,
+    context_slot,
+    time_taken,
+    slippage_mode,
+    fixed,
+    auto,
 }
 
 #[derive(Error, Debug)]
@@ -27,158 +81,2798 @@ pub enum JupSwapError {
     Swap(String),
     #[error("Unknown Error: {0}")]
     Unknown(String),
+    #[error("No route: {0}")]
+    NoRoute(String),
+    /// `PIN_ROUTE` is set and a re-quote could not reproduce the original route
+    #[error("Route unavailable: {0}")]
+    RouteUnavailable(String),
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+    /// The quote request kept failing at the transport level
+    #[error("Quote failed: {0}")]
+    QuoteFailed(String),
+    /// The RPC refused the transaction, so it was never submitted
+    #[error("Send failed: {0}")]
+    SendFailed(String),
+    #[error("Output too small: {out_amount} is below the minimum of {minimum}")]
+    OutputTooSmall { out_amount: u64, minimum: u64 },
+    #[error("Input too large: {in_amount} exceeds the ExactOut maximum of {maximum}")]
+    InputTooLarge { in_amount: u64, maximum: u64 },
+    /// `price_impact_pct` is `None` when Jupiter's value is unreadable, which
+    /// counts as too high
+    #[error("Price impact {price_impact_pct:?} exceeds the maximum of {maximum}")]
+    PriceImpactTooHigh { price_impact_pct: Option<f64>, maximum: f64 },
+    #[error("Malformed swap instruction: {0}")]
+    MalformedSwapInstruction(String),
+    /// The RPC timed out; the transaction may still land under this signature
+    #[error("Send timed out: {0:?}")]
+    SendTimeout(Option<String>),
+    #[error("Estimated fee of {estimated} lamports exceeds the maximum of {maximum}")]
+    FeeTooHigh { estimated: u64, maximum: u64 },
+    #[error("Not accepting new swaps while draining")]
+    Draining,
+    #[error("Cancelled before submission")]
+    Cancelled,
+    /// Never landed and its blockhash expired; safe to retry
+    #[error("Transaction dropped: {0}")]
+    Dropped(String),
+    /// The keypair file is missing or not a Solana keypair
+    #[error("Could not read keypair file {path}: {reason}")]
+    KeypairFile { path: String, reason: String },
+    /// A signing key that is neither a JSON byte array nor base58
+    #[error("Invalid keypair: {0}")]
+    InvalidKeypair(String),
+    #[error("Jupiter API: {message} ({})", code.as_deref().unwrap_or("no error code"))]
+    JupiterApi { code: Option<String>, message: String },
+    /// Jupiter kept answering 429 after the retries; `retry_after_ms` is its
+    /// `Retry-After` hint when it sent one
+    #[error("Rate limited by Jupiter, retry after {retry_after_ms:?} ms")]
+    RateLimited { retry_after_ms: Option<u64> },
+    /// Jupiter refused to build a swap for a quote that is too old; re-quote,
+    /// or set `SWAP_REQUOTE_EXPIRED=true` to do it once automatically
+    #[error("Quote expired: {0}")]
+    QuoteExpired(String),
+    /// Jupiter only routes on mainnet, so swaps are refused on other clusters
+    #[error("Unsupported cluster: {0}")]
+    UnsupportedCluster(String),
+}
+
+impl JupSwapError {
+    /// Whether a pre-flight guard stopped the swap before anything was sent
+    fn is_rejection(&self) -> bool {
+        matches!(
+            self,
+            JupSwapError::OutputTooSmall { .. }
+                | JupSwapError::InputTooLarge { .. }
+                | JupSwapError::PriceImpactTooHigh { .. }
+                | JupSwapError::FeeTooHigh { .. }
+                | JupSwapError::KeypairFile { .. }
+                | JupSwapError::InvalidKeypair(_)
+                | JupSwapError::UnsupportedCluster(_)
+                | JupSwapError::Draining
+                | JupSwapError::Cancelled
+        )
+    }
+}
+
+impl From<jup_ag::Error> for JupSwapError {
+    fn from(error: jup_ag::Error) -> Self {
+        match error {
+            jup_ag::Error::JupiterApi { code, message } => JupSwapError::JupiterApi { code, message },
+            jup_ag::Error::NoRoute => JupSwapError::NoRoute(error.to_string()),
+            jup_ag::Error::QuoteExpired(message) => JupSwapError::QuoteExpired(message),
+            jup_ag::Error::RateLimited { retry_after } => JupSwapError::RateLimited {
+                retry_after_ms: retry_after.map(|duration| duration.as_millis() as u64),
+            },
+            error => JupSwapError::Swap(error.to_string()),
+        }
+    }
+}
+
+impl From<String> for JupSwapError {
+    fn from(message: String) -> Self {
+        JupSwapError::Swap(message)
+    }
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct SwapResult {
+    operation_id: String,
+    signature: String,
+    status: LandedStatus,
+    /// Slot the transaction landed in, when the RPC reported it
+    slot: Option<u64>,
+    in_amount: u64,
+    out_amount: u64,
+    /// What the swap actually paid out, from Jupiter's swap events; `nil`
+    /// when the landed transaction could not be read
+    filled_out_amount: Option<u64>,
+    price_impact_pct: f64,
+    route_summary: String,
+    /// Whether a re-quote swapped through a different route than the first
+    /// quote
+    route_changed: bool,
+    legs: Vec<RouteLeg>,
+    intermediate_amounts: Vec<IntermediateAmount>,
+    metadata: jup_ag::QuoteMetadata,
+    timings: Timings,
+    /// `out_amount / in_amount` in atomic units, as quoted; `nil` when the
+    /// amounts are unreadable or `in_amount` is zero
+    raw_rate: Option<f64>,
+    /// `out_amount / (in_amount + fees)`, see `fee_in_input_mint`
+    effective_rate: Option<f64>,
+    /// The whole swap as canonical JSON when `RESULT_AS_JSON=true`
+    json: Option<String>,
+    /// Advisories about the route, see `FLAGGED_AMMS` and
+    /// `LOW_LIQUIDITY_PRICE_IMPACT_PCT`
+    warnings: Vec<String>,
+    /// The slippage Jupiter settled on, see `DYNAMIC_SLIPPAGE_MAX_BPS`
+    dynamic_slippage_report: Option<SlippageReport>,
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct SlippageReport {
+    slippage_bps: u64,
+    other_amount: Option<u64>,
+    simulated_incurred_slippage_bps: Option<i64>,
+    amplification_ratio: Option<String>,
+    category_name: Option<String>,
+    heuristic_max_slippage_bps: Option<u64>,
+}
+
+impl From<jup_ag::DynamicSlippageReport> for SlippageReport {
+    fn from(report: jup_ag::DynamicSlippageReport) -> Self {
+        SlippageReport {
+            slippage_bps: report.slippage_bps,
+            other_amount: report.other_amount,
+            simulated_incurred_slippage_bps: report.simulated_incurred_slippage_bps,
+            amplification_ratio: report.amplification_ratio,
+            category_name: report.category_name,
+            heuristic_max_slippage_bps: report.heuristic_max_slippage_bps,
+        }
+    }
+}
+
+/// The canonical JSON record of a confirmed swap. Pubkeys and amounts are
+/// strings so nothing loses precision on the way to storage.
+#[derive(Serialize)]
+struct SwapRecord<'a> {
+    outcome: &'static str,
+    operation_id: &'a str,
+    signature: &'a str,
+    input_mint: String,
+    output_mint: String,
+    in_amount: &'a str,
+    out_amount: &'a str,
+    other_amount_threshold: &'a str,
+    slippage_bps: u64,
+    price_impact_pct: &'a str,
+    route_summary: &'a str,
+    legs: &'a [RouteLeg],
+    warnings: &'a [String],
+    estimated_fee_lamports: String,
+    prioritization_fee_lamports: String,
+    effective_rate: Option<f64>,
+    timings: &'a Timings,
+}
+
+/// How far a landed swap has progressed, `:confirmed` or `:finalized`
+#[derive(Clone, Copy)]
+pub enum LandedStatus {
+    Confirmed,
+    Finalized,
+}
+
+impl Encoder for LandedStatus {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            LandedStatus::Confirmed => confirmed().encode(env),
+            LandedStatus::Finalized => finalized().encode(env),
+        }
+    }
+}
+
+/// Milliseconds spent in each phase of a swap
+#[derive(rustler::NifMap, Serialize, Default)]
+#[rustler(encode)]
+pub struct Timings {
+    quote_ms: u64,
+    instructions_ms: u64,
+    alt_ms: u64,
+    simulate_ms: u64,
+    send_ms: u64,
+    confirm_ms: u64,
+}
+
+/// How a swap ended. Every outcome encodes as `{tag, map}` and carries the
+/// operation id.
+pub enum SwapOutcome {
+    /// `{:confirmed, result}`
+    Confirmed(Box<SwapResult>),
+    /// `{:submitted, ...}`: sent, but confirmation was inconclusive; the
+    /// caller should verify the signature later instead of re-sending
+    Submitted(SubmittedSwap),
+    /// `{:simulated, ...}`: `DRY_RUN=true`, simulated and not sent
+    Simulated(SimulatedSwap),
+    /// `{:rejected, ...}`: invalid arguments or a pre-flight guard stopped
+    /// the swap before sending
+    Rejected(SwapFailure),
+    /// `{:failed, ...}`
+    Failed(SwapFailure),
+}
+
+impl Encoder for SwapOutcome {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            SwapOutcome::Confirmed(result) => (confirmed(), result.as_ref()).encode(env),
+            SwapOutcome::Submitted(submitted_swap) => (submitted(), submitted_swap).encode(env),
+            SwapOutcome::Simulated(simulated_swap) => (simulated(), simulated_swap).encode(env),
+            SwapOutcome::Rejected(failure) => (rejected(), failure).encode(env),
+            SwapOutcome::Failed(failure) => (failed(), failure).encode(env),
+        }
+    }
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct SubmittedSwap {
+    operation_id: String,
+    signature: String,
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct SimulatedSwap {
+    operation_id: String,
+    logs: Vec<String>,
+    units_consumed: Option<u64>,
+    /// The simulation error, from `simulate_swap` only; `DRY_RUN` fails instead
+    error: Option<String>,
+}
+
+/// A failed swap, tagged with the operation id so every attempt of the same
+/// logical swap can be correlated in the logs
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct SwapFailure {
+    operation_id: String,
+    reason: JupSwapError,
+}
+
+/// Both legs of a round trip A→B→A. A direction without a route is `nil`.
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct RoundTripQuote {
+    forward_out_amount: Option<u64>,
+    backward_out_amount: Option<u64>,
+    /// How much B must rise against A, in percent, for the round trip to
+    /// recover the spread and swap fees
+    break_even_pct: Option<f64>,
+}
+
+/// The better of a direct-only and a fully routed quote
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct BestQuote {
+    quote: QuotePreview,
+    /// Whether the direct-only quote was chosen
+    only_direct_routes: bool,
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct TokenPrice {
+    id: String,
+    mint_symbol: String,
+    vs_token: String,
+    vs_token_symbol: String,
+    price: f64,
+}
+
+/// A quote as `%JupSwap.Quote{}`, with mints as base58 strings and amounts
+/// as integers
+#[derive(rustler::NifStruct)]
+#[module = "JupSwap.Quote"]
+pub struct QuotePreview {
+    input_mint: String,
+    output_mint: String,
+    in_amount: u64,
+    out_amount: u64,
+    other_amount_threshold: u64,
+    price_impact_pct: f64,
+    slippage_bps: u64,
+    swap_mode: String,
+    route_summary: String,
+    labels: Vec<String>,
+    route_plan: Vec<RoutePlanStep>,
+    /// The advisories a swap of this quote would log, see `quote_warnings`
+    warnings: Vec<String>,
+}
+
+#[derive(rustler::NifStruct)]
+#[module = "JupSwap.RoutePlan"]
+pub struct RoutePlanStep {
+    percent: u64,
+    swap_info: SwapInfo,
+}
+
+#[derive(rustler::NifStruct)]
+#[module = "JupSwap.SwapInfo"]
+pub struct SwapInfo {
+    amm_key: String,
+    label: String,
+    input_mint: String,
+    output_mint: String,
+    in_amount: u64,
+    out_amount: u64,
+    fee_amount: u64,
+    fee_mint: String,
+}
+
+impl From<&jup_ag::RoutePlan> for RoutePlanStep {
+    fn from(step: &jup_ag::RoutePlan) -> Self {
+        let info = &step.swap_info;
+        RoutePlanStep {
+            percent: step.percent,
+            swap_info: SwapInfo {
+                amm_key: info.amm_key.to_string(),
+                label: info.label.clone(),
+                input_mint: info.input_mint.to_string(),
+                output_mint: info.output_mint.to_string(),
+                in_amount: info.in_amount.parse().unwrap_or_default(),
+                out_amount: info.out_amount.parse().unwrap_or_default(),
+                fee_amount: info.fee_amount.parse().unwrap_or_default(),
+                fee_mint: info.fee_mint.to_string(),
+            },
+        }
+    }
+}
+
+impl QuotePreview {
+    fn new(quote: &jup_ag::Quote) -> Self {
+        QuotePreview {
+            input_mint: quote.input_mint.to_string(),
+            output_mint: quote.output_mint.to_string(),
+            in_amount: quote.in_amount_u64().unwrap_or_default(),
+            out_amount: quote.out_amount_u64().unwrap_or_default(),
+            other_amount_threshold: quote.other_amount_threshold.parse().unwrap_or_default(),
+            price_impact_pct: quote.price_impact().unwrap_or_default(),
+            slippage_bps: quote.slippage_bps,
+            swap_mode: quote.swap_mode.to_string(),
+            route_summary: jup_ag::route_summary(quote),
+            labels: jup_ag::route_dexes(quote),
+            route_plan: quote.route_plan.iter().map(RoutePlanStep::from).collect(),
+            warnings: quote_warnings(quote),
+        }
+    }
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct RuntimeStats {
+    in_flight_swaps: usize,
+    worker_threads: usize,
+    alive_tasks: usize,
+    healthy: bool,
+}
+
+/// Latency distribution of successful quotes; a percentile is `nil` when
+/// every request failed. No-route responses count as errors.
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct QuoteBenchmark {
+    requests: usize,
+    errors: usize,
+    error_rate: f64,
+    p50_ms: Option<f64>,
+    p90_ms: Option<f64>,
+    p99_ms: Option<f64>,
+}
+
+#[derive(rustler::NifMap, Serialize)]
+#[rustler(encode)]
+pub struct RouteLeg {
+    hop: usize,
+    label: String,
+    input_mint: String,
+    output_mint: String,
+    in_amount: String,
+    out_amount: String,
+    percent: u64,
+}
+
+#[derive(rustler::NifMap)]
+#[rustler(encode)]
+pub struct IntermediateAmount {
+    mint: String,
+    amount: u64,
+}
+
+impl SwapResult {
+    fn new(
+        operation_id: String,
+        signature: String,
+        quote: &jup_ag::Quote,
+        metadata: jup_ag::QuoteMetadata,
+        timings: Timings,
+    ) -> Self {
+        let legs = jup_ag::route_hops(quote)
+            .iter()
+            .enumerate()
+            .flat_map(|(hop, legs)| {
+                legs.iter().map(move |leg| RouteLeg {
+                    hop,
+                    label: leg.swap_info.label.clone(),
+                    input_mint: leg.swap_info.input_mint.to_string(),
+                    output_mint: leg.swap_info.output_mint.to_string(),
+                    in_amount: leg.swap_info.in_amount.clone(),
+                    out_amount: leg.swap_info.out_amount.clone(),
+                    percent: leg.percent,
+                })
+            })
+            .collect();
+
+        let intermediate_amounts = jup_ag::intermediate_amounts(quote)
+            .into_iter()
+            .map(|(mint, amount)| IntermediateAmount {
+                mint: mint.to_string(),
+                amount,
+            })
+            .collect();
+
+        SwapResult {
+            operation_id,
+            signature,
+            status: LandedStatus::Confirmed,
+            slot: None,
+            in_amount: quote.in_amount_u64().unwrap_or_default(),
+            out_amount: quote.out_amount_u64().unwrap_or_default(),
+            filled_out_amount: None,
+            price_impact_pct: quote.price_impact().unwrap_or_default(),
+            route_summary: jup_ag::route_summary(quote),
+            route_changed: false,
+            legs,
+            intermediate_amounts,
+            metadata,
+            timings,
+            raw_rate: quoted_rate(quote, 0),
+            effective_rate: None,
+            json: None,
+            warnings: Vec::new(),
+            dynamic_slippage_report: None,
+        }
+    }
+
+    fn to_json(
+        &self,
+        quote: &jup_ag::Quote,
+        estimated_fee_lamports: u64,
+        prioritization_fee_lamports: u64,
+    ) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&SwapRecord {
+            outcome: "confirmed",
+            operation_id: &self.operation_id,
+            signature: &self.signature,
+            input_mint: quote.input_mint.to_string(),
+            output_mint: quote.output_mint.to_string(),
+            in_amount: &quote.in_amount,
+            out_amount: &quote.out_amount,
+            other_amount_threshold: &quote.other_amount_threshold,
+            slippage_bps: quote.slippage_bps,
+            price_impact_pct: &quote.price_impact_pct,
+            route_summary: &self.route_summary,
+            legs: &self.legs,
+            warnings: &self.warnings,
+            estimated_fee_lamports: estimated_fee_lamports.to_string(),
+            prioritization_fee_lamports: prioritization_fee_lamports.to_string(),
+            effective_rate: self.effective_rate,
+            timings: &self.timings,
+        })
+    }
+}
+
+impl Encoder for jup_ag::QuoteMetadata {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        let mode = match self.slippage_mode {
+            jup_ag::SlippageMode::Fixed => fixed(),
+            jup_ag::SlippageMode::Auto => auto(),
+        };
+
+        Term::map_from_pairs(
+            env,
+            &[
+                (context_slot().encode(env), self.context_slot.encode(env)),
+                (time_taken().encode(env), self.time_taken.encode(env)),
+                (slippage_mode().encode(env), mode.encode(env)),
+            ],
+        )
+        .unwrap()
+    }
 }
 
 impl Encoder for JupSwapError {
     fn encode<'b>(&self, env: Env<'b>) -> Term<'b> {
-        format!("{self}").encode(env)
+        match self {
+            JupSwapError::OutputTooSmall { .. } => output_too_small().encode(env),
+            JupSwapError::InputTooLarge { .. } => input_too_large().encode(env),
+            JupSwapError::PriceImpactTooHigh { price_impact_pct, .. } => {
+                (price_impact_too_high(), price_impact_pct).encode(env)
+            }
+            JupSwapError::MalformedSwapInstruction(_) => malformed_swap_instruction().encode(env),
+            JupSwapError::SendTimeout(signature) => (send_timeout(), signature).encode(env),
+            JupSwapError::FeeTooHigh { estimated, .. } => (fee_too_high(), estimated).encode(env),
+            JupSwapError::Draining => draining().encode(env),
+            JupSwapError::Cancelled => cancelled().encode(env),
+            JupSwapError::Dropped(signature) => (dropped(), signature).encode(env),
+            JupSwapError::JupiterApi { code, message } => (jupiter_api(), code, message).encode(env),
+            JupSwapError::QuoteExpired(_) => quote_expired().encode(env),
+            JupSwapError::RateLimited { retry_after_ms } => (rate_limited(), retry_after_ms).encode(env),
+            JupSwapError::UnsupportedCluster(message) => (unsupported_cluster(), message).encode(env),
+            JupSwapError::KeypairFile { path, reason } => (keypair_file(), path, reason).encode(env),
+            JupSwapError::InvalidKeypair(message) => (invalid_keypair(), message).encode(env),
+            JupSwapError::Swap(message) => (swap(), message).encode(env),
+            JupSwapError::Unknown(message) => (unknown(), message).encode(env),
+            JupSwapError::NoRoute(message) => (no_route(), message).encode(env),
+            JupSwapError::RouteUnavailable(message) => (route_unavailable(), message).encode(env),
+            JupSwapError::InvalidAmount(message) => (invalid_amount(), message).encode(env),
+            JupSwapError::QuoteFailed(message) => (quote_failed(), message).encode(env),
+            JupSwapError::SendFailed(message) => (send_failed(), message).encode(env),
+        }
     }
 }
 
-mod jup_ag;
+pub mod jup_ag;
+pub mod mints;
+pub mod slippage;
+mod blockhash;
+mod in_flight;
+mod swap_event;
+
+const DEFAULT_MIN_SOL_RESERVE_LAMPORTS: u64 = 10_000_000;
+const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+const MAX_DECIMALS: u8 = 18;
+const RPC_HEALTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const JUPITER_HEALTH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+/// 0.001 SOL, the quote `jupiter_healthy` asks for
+const JUPITER_HEALTH_AMOUNT: u64 = 1_000_000;
+const QUOTE_ATTEMPTS: u32 = 3;
+const QUOTE_INITIAL_BACKOFF: std::time::Duration = std::time::Duration::from_millis(200);
+/// Jupiter program error `SlippageToleranceExceeded` (0x1771)
+const JUPITER_SLIPPAGE_EXCEEDED: u32 = 6001;
+const DEFAULT_MAX_RETRIES: usize = 2;
+const FINALIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+const FINALIZE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+const RUNTIME_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+/// The wrapped SOL mint, the only way Jupiter routes SOL
+const NATIVE_SOL_MINT: Pubkey = mints::SOL;
+/// What some wallets and APIs use for native SOL; Jupiter does not know it
+const NATIVE_SOL_PLACEHOLDER: Pubkey = solana_sdk::pubkey!("11111111111111111111111111111111");
+const TOKEN_2022_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
 
-static INIT: Once = Once::new();
-static mut RUNTIME: Option<Runtime> = None;
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
 
 fn get_runtime() -> &'static Runtime {
-    INIT.call_once(|| {
-        let rt = Runtime::new().expect("Failed to create runtime");
-        unsafe {
-            RUNTIME = Some(rt);
+    RUNTIME.get_or_init(|| Runtime::new().expect("Failed to create runtime"))
+}
+
+/// Shared limit on quote requests, `JUP_QUOTE_RATE_LIMIT` per second. Unset
+/// means unlimited.
+fn quote_limiter() -> Option<&'static RateLimiter<NotKeyed, InMemoryState, DefaultClock>> {
+    static LIMITER: OnceLock<Option<RateLimiter<NotKeyed, InMemoryState, DefaultClock>>> = OnceLock::new();
+
+    LIMITER
+        .get_or_init(|| {
+            let per_second = std::env::var("JUP_QUOTE_RATE_LIMIT").ok()?.parse::<NonZeroU32>().ok()?;
+            Some(RateLimiter::direct(Quota::per_second(per_second)))
+        })
+        .as_ref()
+}
+
+/// Waits for the quote rate limiter, if any
+async fn throttle_quote() {
+    if let Some(limiter) = quote_limiter() {
+        limiter.until_ready().await;
+    }
+}
+
+/// Fetches a quote, retrying transport failures and non-JSON responses (e.g. a
+/// 502 from Jupiter's load balancer) with exponential backoff. No-route and
+/// Jupiter API errors are returned right away.
+async fn fetch_quote(operation_id: &str, client: &reqwest::Client, url: &str) -> jup_ag::Result<jup_ag::Quote> {
+    let mut backoff = QUOTE_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        throttle_quote().await;
+        match jup_ag::get_quote(client, url.to_string()).await {
+            Err(jup_ag::Error::Reqwest(e)) if attempt < QUOTE_ATTEMPTS => {
+                tracing::warn!(operation_id, attempt, ?backoff, error = %e, "quote attempt failed, retrying");
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+                attempt += 1;
+            }
+            result => return result,
         }
-    });
-    unsafe { RUNTIME.as_ref().unwrap() }
+    }
 }
 
-#[rustler::nif(schedule = "DirtyCpu")]
-fn quick_swap(token_to: String, token_from: String, amount: u64) -> Result<String, String> {
-    let token_from_pubkey = Pubkey::try_from(token_from.as_str()).unwrap();
-    let token_to_pubkey = Pubkey::try_from(token_to.as_str()).unwrap();
-    
-    do_quick_swap(token_from_pubkey, token_to_pubkey, amount)
+#[rustler::nif(schedule = "DirtyIo")]
+#[allow(clippy::too_many_arguments)]
+fn quick_swap(
+    token_to: String,
+    token_from: String,
+    amount: u64,
+    only_direct_routes: Option<bool>,
+    slippage_bps: Option<u64>,
+    skip_preflight: Option<bool>,
+    max_retries: Option<usize>,
+    min_out_amount: Option<u64>,
+) -> SwapOutcome {
+    let swap_options = SwapOptions {
+        skip_preflight,
+        max_retries,
+        min_out_amount,
+        ..SwapOptions::default()
+    };
+    run_quick_swap(
+        load_keypair("SOLANA_PRIVATE_KEY"),
+        token_to,
+        token_from,
+        amount,
+        only_direct_routes,
+        slippage_bps,
+        swap_options,
+    )
 }
 
-fn do_quick_swap(token_from: Pubkey, token_to: Pubkey, amount: u64) -> Result<String, String> {
-    get_runtime().block_on(async {
-        let client = reqwest::Client::builder().build().unwrap();
-        let from_url = jup_ag::quote_url(
-            token_from,
+/// `quick_swap/3` without blocking a scheduler: the swap runs on its own
+/// thread and `{:jup_swap_result, reference, outcome}` is sent to the calling
+/// process when it finishes. `reference` is any term the caller uses to
+/// match the reply, see `JupSwap.Native.quick_swap_async/3`.
+#[rustler::nif]
+fn start_quick_swap<'a>(env: Env<'a>, reference: Term<'a>, token_to: String, token_from: String, amount: u64) -> Atom {
+    let pid = env.pid();
+    let mut owned_env = OwnedEnv::new();
+    let reference = owned_env.save(reference);
+
+    // Not a tokio task: do_quick_swap blocks on the runtime itself
+    std::thread::spawn(move || {
+        let outcome = run_quick_swap(
+            load_keypair("SOLANA_PRIVATE_KEY"),
             token_to,
-            amount.to_string(),
-            true,
-            Some(0),
-            "ExactIn".to_string()
+            token_from,
+            amount,
+            None,
+            None,
+            SwapOptions::default(),
         );
-        let from_resp = client.get(from_url).send().await.unwrap();
-        let from_json = from_resp.json().await.unwrap();
-        let from_result: jup_ag::Result<jup_ag::Quote> = jup_ag::maybe_jupiter_api_error(from_json);
-        let from_quote_result = match from_result {
-            Ok(r) => r,
-            Err(_e) => jup_ag::Quote::default(),
-        };
-        let from_quote = from_quote_result;
-        let mut combined_route_plans: Vec<jup_ag::RoutePlan> = Vec::new();
-
-        combined_route_plans.append(&mut from_quote.clone().route_plan);
-
-        let slippage_bps = std::env::var("SLIPPAGE_BPS").map(|s| s.parse::<u64>().unwrap()).unwrap_or(20);
-
-        let combined_quote = jup_ag::Quote {
-            input_mint: from_quote.input_mint,
-            output_mint: from_quote.output_mint,
-            in_amount: from_quote.in_amount,
-            out_amount: from_quote.out_amount,
-            route_plan: combined_route_plans,
-            slippage_bps: slippage_bps,
-            price_impact_pct: from_quote.price_impact_pct,
-            other_amount_threshold: from_quote.other_amount_threshold,
-            swap_mode: "ExactIn".to_string(),
+        let _ = owned_env.send_and_clear(&pid, |env| (jup_swap_result(), reference.load(env), outcome).encode(env));
+    });
+
+    ok()
+}
+
+/// Builds and simulates the swap `quick_swap/3` would send, signed with the
+/// key in `key_env_var`, without broadcasting it. Returns
+/// `{:simulated, %{logs: ..., units_consumed: ..., error: ...}}` whether or
+/// not the simulation succeeded.
+#[rustler::nif(schedule = "DirtyIo")]
+fn simulate_swap(token_to: String, token_from: String, amount: u64, key_env_var: String) -> SwapOutcome {
+    let swap_options = SwapOptions {
+        simulate_only: true,
+        ..SwapOptions::default()
+    };
+    run_quick_swap(load_keypair(&key_env_var), token_to, token_from, amount, None, None, swap_options)
+}
+
+/// Per-call swap options. For sending, `nil` falls back to
+/// `TRANSACTION_SKIP_PREFLIGHT` and `TRANSACTION_MAX_RETRIES`, and an unset
+/// or unparseable variable to skipping preflight (the swap was already
+/// simulated) with at most `DEFAULT_MAX_RETRIES` node retries.
+#[derive(Clone, Copy, Default)]
+struct SwapOptions {
+    skip_preflight: Option<bool>,
+    max_retries: Option<usize>,
+    /// Absolute floor on the quoted output, overriding `MIN_OUTPUT_AMOUNT`
+    min_out_amount: Option<u64>,
+    /// Stop after the simulation, whatever its result
+    simulate_only: bool,
+}
+
+impl SwapOptions {
+    fn config(self, rpc_client: &RpcClient) -> RpcSendTransactionConfig {
+        RpcSendTransactionConfig {
+            skip_preflight: self
+                .skip_preflight
+                .or_else(|| std::env::var("TRANSACTION_SKIP_PREFLIGHT").ok()?.parse().ok())
+                .unwrap_or(true),
+            preflight_commitment: Some(rpc_client.commitment().commitment),
+            max_retries: self
+                .max_retries
+                .or_else(|| std::env::var("TRANSACTION_MAX_RETRIES").ok()?.parse().ok())
+                .or(Some(DEFAULT_MAX_RETRIES)),
+            ..RpcSendTransactionConfig::default()
+        }
+    }
+}
+
+/// `quick_swap/3` signing with `secret_key` (a JSON byte array or base58)
+/// instead of `SOLANA_PRIVATE_KEY`, so the key never has to be in the
+/// process environment
+#[rustler::nif(schedule = "DirtyIo")]
+fn quick_swap_with_key(token_to: String, token_from: String, amount: u64, secret_key: String) -> SwapOutcome {
+    run_quick_swap(parse_keypair(&secret_key), token_to, token_from, amount, None, None, SwapOptions::default())
+}
+
+/// `quick_swap/3` signing with the keypair file at `keypair_path`, in the
+/// JSON format written by `solana-keygen`. The path is used as given, so
+/// expand `~` on the Elixir side.
+#[rustler::nif(schedule = "DirtyIo")]
+fn quick_swap_with_keyfile(token_to: String, token_from: String, amount: u64, keypair_path: String) -> SwapOutcome {
+    let keypair = read_keypair_file(&keypair_path).map_err(|e| JupSwapError::KeypairFile {
+        path: keypair_path,
+        reason: e.to_string(),
+    });
+    run_quick_swap(keypair, token_to, token_from, amount, None, None, SwapOptions::default())
+}
+
+fn run_quick_swap(
+    keypair: Result<Keypair, JupSwapError>,
+    token_to: String,
+    token_from: String,
+    amount: u64,
+    only_direct_routes: Option<bool>,
+    slippage_bps: Option<u64>,
+    swap_options: SwapOptions,
+) -> SwapOutcome {
+    let operation_id = new_operation_id();
+    let (token_from_pubkey, token_to_pubkey) =
+        match (parse_pubkey("input mint", &token_from), parse_pubkey("output mint", &token_to)) {
+            (Ok(token_from), Ok(token_to)) => (token_from, token_to),
+            (Err(reason), _) | (_, Err(reason)) => return SwapOutcome::Rejected(SwapFailure { operation_id, reason }),
         };
+    if amount == 0 {
+        let reason = JupSwapError::InvalidAmount("amount must be > 0".to_string());
+        return SwapOutcome::Rejected(SwapFailure { operation_id, reason });
+    }
+    let Some(_in_flight) = in_flight::register(&operation_id) else {
+        return SwapOutcome::Rejected(SwapFailure { operation_id, reason: JupSwapError::Draining });
+    };
+
+    let only_direct_routes = only_direct_routes.unwrap_or_else(only_direct_routes_default);
+
+    let quote_source = QuoteSource::Fetch { only_direct_routes, slippage_bps };
+    let result = keypair.and_then(|keypair| {
+        do_quick_swap(&operation_id, keypair, token_from_pubkey, token_to_pubkey, amount, quote_source, swap_options)
+    });
+    swap_outcome(operation_id.clone(), result)
+}
+
+/// Executes a quote fetched elsewhere (e.g. with `get_quote` or straight from
+/// Jupiter), skipping the quote request. Signs with the key in the
+/// `key_env_var` environment variable. A re-quote after a slippage failure
+/// fetches a fresh quote for the same mints and amount. A quote Jupiter calls
+/// too old fails with `:quote_expired`, or is re-quoted once with
+/// `SWAP_REQUOTE_EXPIRED=true`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn swap_from_quote(quote_json: String, key_env_var: String) -> SwapOutcome {
+    let operation_id = new_operation_id();
+    let quote: jup_ag::Quote = match serde_json::from_str(&quote_json) {
+        Ok(quote) => quote,
+        Err(e) => {
+            let reason = format!("Invalid quote: {}", e).into();
+            return SwapOutcome::Rejected(SwapFailure { operation_id, reason });
+        }
+    };
+    let amount = match quote.swap_mode {
+        jup_ag::SwapMode::ExactIn => &quote.in_amount,
+        jup_ag::SwapMode::ExactOut => &quote.out_amount,
+    };
+    let Ok(amount) = amount.parse::<u64>() else {
+        let reason = format!("Invalid quote amount: {}", amount).into();
+        return SwapOutcome::Rejected(SwapFailure { operation_id, reason });
+    };
+    let Some(_in_flight) = in_flight::register(&operation_id) else {
+        return SwapOutcome::Rejected(SwapFailure { operation_id, reason: JupSwapError::Draining });
+    };
 
-        let swap_config = jup_ag::SwapConfig {
-            wrap_and_unwrap_sol: Some(false),
-            fee_account: None,
-            token_ledger: None
+    let (token_from, token_to) = (quote.input_mint, quote.output_mint);
+    let result = load_keypair(&key_env_var).and_then(|keypair| {
+        let quote_source = QuoteSource::Prefetched(Box::new(quote));
+        do_quick_swap(&operation_id, keypair, token_from, token_to, amount, quote_source, SwapOptions::default())
+    });
+    swap_outcome(operation_id.clone(), result)
+}
+
+fn swap_outcome(operation_id: String, result: Result<SwapOutcome, JupSwapError>) -> SwapOutcome {
+    match result {
+        Ok(outcome) => outcome,
+        Err(reason) if reason.is_rejection() => SwapOutcome::Rejected(SwapFailure { operation_id, reason }),
+        Err(reason) => SwapOutcome::Failed(SwapFailure { operation_id, reason }),
+    }
+}
+
+/// `SWAP_MODE`, `ExactIn` (the default) or `ExactOut`. In ExactOut mode the
+/// amount passed to `quick_swap` is the output amount.
+fn swap_mode() -> Result<jup_ag::SwapMode, JupSwapError> {
+    match std::env::var("SWAP_MODE") {
+        Ok(mode) => mode.parse().map_err(|e| format!("Invalid SWAP_MODE: {}", e).into()),
+        Err(_) => Ok(jup_ag::SwapMode::default()),
+    }
+}
+
+/// `PRIORITY_LEVEL` (`medium`, `high` or `veryHigh`) with a
+/// `PRIORITY_MAX_LAMPORTS` cap, lowered to `PRIORITY_FEE_CAP_LAMPORTS` when
+/// that is smaller. Unset leaves the priority fee to Jupiter.
+fn priority_fee_lamports() -> Result<Option<jup_ag::PrioritizationFeeLamports>, JupSwapError> {
+    let Ok(priority_level) = std::env::var("PRIORITY_LEVEL") else {
+        return Ok(None);
+    };
+    let priority_level = priority_level.parse::<jup_ag::PriorityLevel>()?;
+    let max_lamports = match std::env::var("PRIORITY_MAX_LAMPORTS") {
+        Ok(s) => Some(
+            s.parse::<u64>()
+                .map_err(|e| format!("Invalid PRIORITY_MAX_LAMPORTS {}: {}", s, e))?,
+        ),
+        Err(_) => None,
+    };
+    let max_lamports = match (max_lamports, priority_fee_cap()?) {
+        (Some(max_lamports), Some(cap)) => max_lamports.min(cap),
+        (Some(max_lamports), None) => max_lamports,
+        (None, Some(cap)) => cap,
+        (None, None) => {
+            return Err("PRIORITY_LEVEL requires PRIORITY_MAX_LAMPORTS or PRIORITY_FEE_CAP_LAMPORTS"
+                .to_string()
+                .into())
+        }
+    };
+
+    Ok(Some(jup_ag::PrioritizationFeeLamports {
+        priority_level_with_max_lamports: jup_ag::PriorityLevelWithMaxLamports {
+            priority_level,
+            max_lamports,
+        },
+    }))
+}
+
+/// `PRIORITY_FEE_CAP_LAMPORTS`, a hard ceiling on the prioritization fee of
+/// any single swap, whatever Jupiter bids
+fn priority_fee_cap() -> Result<Option<u64>, JupSwapError> {
+    match std::env::var("PRIORITY_FEE_CAP_LAMPORTS") {
+        Ok(s) => s
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|e| format!("Invalid PRIORITY_FEE_CAP_LAMPORTS {}: {}", s, e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `DESTINATION_TOKEN_ACCOUNT`, a token account other than the wallet's ATA
+/// to receive the output
+fn destination_token_account() -> Result<Option<Pubkey>, JupSwapError> {
+    match std::env::var("DESTINATION_TOKEN_ACCOUNT") {
+        Ok(s) => Pubkey::try_from(s.trim())
+            .map(Some)
+            .map_err(|e| format!("Invalid DESTINATION_TOKEN_ACCOUNT: {}", e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// The swap options shared by every swap, from the environment
+fn swap_config(
+    wrap_and_unwrap_sol: bool,
+    fee_account: Option<Pubkey>,
+    destination_token_account: Option<Pubkey>,
+) -> Result<jup_ag::SwapConfig, JupSwapError> {
+    Ok(jup_ag::SwapConfig {
+        wrap_and_unwrap_sol: Some(wrap_and_unwrap_sol),
+        fee_account,
+        use_token_ledger: use_token_ledger().then_some(true),
+        destination_token_account,
+        priority_fee_lamports: priority_fee_lamports()?,
+        dynamic_slippage: dynamic_slippage()?,
+        as_legacy_transaction: as_legacy_transaction().then_some(true),
+    })
+}
+
+/// `FEE_ACCOUNT`, the referral token account collecting platform fees
+fn fee_account() -> Result<Option<Pubkey>, JupSwapError> {
+    match std::env::var("FEE_ACCOUNT") {
+        Ok(s) => Pubkey::try_from(s.trim())
+            .map(Some)
+            .map_err(|e| format!("Invalid FEE_ACCOUNT: {}", e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `FEE_PAYER_PRIVATE_KEY`, a separate account paying the transaction fees
+/// (e.g. a relayer). Only the `/swap-instructions` path can use it, since
+/// `/swap` returns a transaction already paid by the swapper.
+fn fee_payer() -> Result<Option<Keypair>, JupSwapError> {
+    let Ok(key_string) = std::env::var("FEE_PAYER_PRIVATE_KEY") else {
+        return Ok(None);
+    };
+    if !use_swap_instructions() {
+        return Err("FEE_PAYER_PRIVATE_KEY requires USE_SWAP_INSTRUCTIONS=true".to_string().into());
+    }
+
+    parse_keypair(&key_string).map(Some)
+}
+
+/// `DEXES` (only these) or `EXCLUDE_DEXES` (never these), comma separated
+/// Jupiter labels. Setting both is an error.
+fn dex_filters() -> Result<jup_ag::QuoteConfig, JupSwapError> {
+    let (dexes, exclude_dexes) = (env_list("DEXES"), env_list("EXCLUDE_DEXES"));
+    if dexes.is_some() && exclude_dexes.is_some() {
+        return Err("DEXES and EXCLUDE_DEXES are mutually exclusive".to_string().into());
+    }
+
+    Ok(jup_ag::QuoteConfig {
+        dexes,
+        exclude_dexes,
+        ..Default::default()
+    })
+}
+
+/// `MAX_ACCOUNTS` caps the accounts a quoted route may use
+fn max_accounts() -> Result<Option<u64>, JupSwapError> {
+    match std::env::var("MAX_ACCOUNTS") {
+        Ok(s) => s
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|e| format!("Invalid MAX_ACCOUNTS {}: {}", s, e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `RESTRICT_INTERMEDIATE_TOKENS=false` widens routing to every intermediate
+/// token, which can find routes for exotic pairs. Unset sends `true`
+/// explicitly rather than relying on Jupiter's default.
+fn restrict_intermediate_tokens() -> Result<Option<bool>, JupSwapError> {
+    match std::env::var("RESTRICT_INTERMEDIATE_TOKENS") {
+        Ok(s) => s
+            .parse::<bool>()
+            .map(Some)
+            .map_err(|e| format!("Invalid RESTRICT_INTERMEDIATE_TOKENS {}: {}", s, e).into()),
+        Err(_) => Ok(Some(true)),
+    }
+}
+
+/// `DYNAMIC_SLIPPAGE_MAX_BPS` asks Jupiter to simulate the swap and pick the
+/// slippage itself, capped at that many bps
+fn dynamic_slippage() -> Result<Option<jup_ag::DynamicSlippage>, JupSwapError> {
+    match std::env::var("DYNAMIC_SLIPPAGE_MAX_BPS") {
+        Ok(s) => s
+            .parse::<u64>()
+            .map(|max_bps| Some(jup_ag::DynamicSlippage { max_bps }))
+            .map_err(|e| format!("Invalid DYNAMIC_SLIPPAGE_MAX_BPS {}: {}", s, e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// `PLATFORM_FEE_BPS`, the referral fee to reserve in the quote. Only valid
+/// with a `FEE_ACCOUNT` to collect it.
+fn platform_fee_bps(fee_account: Option<&Pubkey>) -> Result<Option<u64>, JupSwapError> {
+    let Ok(platform_fee_bps) = std::env::var("PLATFORM_FEE_BPS") else {
+        return Ok(None);
+    };
+    if fee_account.is_none() {
+        return Err("PLATFORM_FEE_BPS requires FEE_ACCOUNT".to_string().into());
+    }
+
+    platform_fee_bps
+        .parse::<u64>()
+        .map(Some)
+        .map_err(|e| format!("Invalid PLATFORM_FEE_BPS {}: {}", platform_fee_bps, e).into())
+}
+
+/// `USE_TOKEN_LEDGER=true` swaps the balance change recorded by Jupiter's
+/// token ledger instruction rather than the quoted amount
+fn use_token_ledger() -> bool {
+    std::env::var("USE_TOKEN_LEDGER").map(|s| s == "true").unwrap_or(false)
+}
+
+/// How many times to re-quote after a slippage failure, from
+/// `SWAP_MAX_REQUOTES` (default 0)
+fn max_requotes() -> Result<u32, JupSwapError> {
+    match std::env::var("SWAP_MAX_REQUOTES") {
+        Ok(s) => s
+            .parse::<u32>()
+            .map_err(|e| format!("Invalid SWAP_MAX_REQUOTES {}: {}", s, e).into()),
+        Err(_) => Ok(0),
+    }
+}
+
+/// `SWAP_REQUOTE_EXPIRED=true` replaces a prefetched quote that Jupiter calls
+/// expired with a fresh one, once, instead of failing with `:quote_expired`
+fn requote_expired() -> bool {
+    std::env::var("SWAP_REQUOTE_EXPIRED").map(|s| s == "true").unwrap_or(false)
+}
+
+/// `PIN_ROUTE=true` keeps re-quotes on the AMMs of the first quote and fails
+/// with `RouteUnavailable` rather than swapping through a different route
+fn pin_route() -> bool {
+    std::env::var("PIN_ROUTE").map(|s| s == "true").unwrap_or(false)
+}
+
+/// `WAIT_FOR_FINALIZED=true` holds a confirmed swap until it is finalized
+fn wait_for_finalized() -> bool {
+    std::env::var("WAIT_FOR_FINALIZED").map(|s| s == "true").unwrap_or(false)
+}
+
+/// `DRY_RUN=true` stops after a successful simulation without sending
+fn dry_run() -> bool {
+    std::env::var("DRY_RUN").map(|s| s == "true").unwrap_or(false)
+}
+
+/// AMM labels or keys to warn about, comma separated in `FLAGGED_AMMS`
+fn flagged_amms() -> Vec<String> {
+    env_list("FLAGGED_AMMS").unwrap_or_default()
+}
+
+/// Advisories about a quote's route: AMMs in `FLAGGED_AMMS` and low liquidity,
+/// see `LOW_LIQUIDITY_PRICE_IMPACT_PCT`
+fn quote_warnings(quote: &jup_ag::Quote) -> Vec<String> {
+    let mut warnings = jup_ag::route_warnings(quote, &flagged_amms());
+    warnings.extend(jup_ag::liquidity_warnings(quote, low_liquidity_price_impact()));
+    warnings
+}
+
+/// `LOW_LIQUIDITY_PRICE_IMPACT_PCT`, the price impact from which a quote gets a
+/// low-liquidity warning, in the units of Jupiter's `priceImpactPct`
+fn low_liquidity_price_impact() -> Option<f64> {
+    std::env::var("LOW_LIQUIDITY_PRICE_IMPACT_PCT")
+        .ok()
+        .and_then(|s| s.parse::<f64>().ok())
+}
+
+/// An optional numeric setting, e.g. `MAX_AUTO_SLIPPAGE_BPS` and
+/// `AUTO_SLIPPAGE_COLLISION_USD_VALUE`, which tune Jupiter's automatic
+/// slippage when no slippage is fixed
+fn env_u64(name: &str) -> Result<Option<u64>, JupSwapError> {
+    match std::env::var(name) {
+        Ok(s) => s
+            .parse::<u64>()
+            .map(Some)
+            .map_err(|e| format!("Invalid {} {}: {}", name, s, e).into()),
+        Err(_) => Ok(None),
+    }
+}
+
+/// A comma separated env var, `None` when unset
+fn env_list(name: &str) -> Option<Vec<String>> {
+    std::env::var(name).ok().map(|s| {
+        s.split(',')
+            .map(|item| item.trim().to_string())
+            .filter(|item| !item.is_empty())
+            .collect()
+    })
+}
+
+/// `RESULT_AS_JSON=true` adds the canonical JSON record to the swap result
+fn result_as_json() -> bool {
+    std::env::var("RESULT_AS_JSON").map(|s| s == "true").unwrap_or(false)
+}
+
+/// `ONLY_DIRECT_ROUTES`, used when the caller passes `nil`. Defaults to
+/// direct routes only.
+fn only_direct_routes_default() -> bool {
+    std::env::var("ONLY_DIRECT_ROUTES").map_or(true, |v| v != "false")
+}
+
+/// A random id for one logical swap. Unlike the signature, it stays the same
+/// across resends.
+fn new_operation_id() -> String {
+    format!("{:032x}", rand::random::<u128>())
+}
+
+/// Where the first quote of a swap comes from. Re-quotes after a slippage
+/// failure are always fetched fresh.
+enum QuoteSource {
+    Fetch {
+        only_direct_routes: bool,
+        slippage_bps: Option<u64>,
+    },
+    /// A quote fetched by the caller, executed as-is
+    Prefetched(Box<jup_ag::Quote>),
+}
+
+/// Reads the signing key from `key_env_var`, falling back to an ephemeral
+/// keypair when it is unset
+fn load_keypair(key_env_var: &str) -> Result<Keypair, JupSwapError> {
+    match std::env::var(key_env_var) {
+        Ok(key_string) => parse_keypair(&key_string),
+        Err(_) => {
+            tracing::warn!(
+                key_env_var,
+                "signing key not set, using an ephemeral keypair; set it to a JSON byte array or a base58 private key"
+            );
+            Ok(Keypair::new())
+        }
+    }
+}
+
+/// Parses a secret key given as a JSON byte array or in base58
+fn parse_keypair(key_string: &str) -> Result<Keypair, JupSwapError> {
+    // First try parsing as JSON array
+    let key_bytes = if key_string.starts_with('[') {
+        serde_json::from_str::<Vec<u8>>(key_string)
+            .map_err(|e| JupSwapError::InvalidKeypair(format!("Failed to parse JSON private key: {}", e)))?
+    } else {
+        // If not JSON, try base58 decode
+        bs58::decode(key_string.trim())
+            .into_vec()
+            .map_err(|e| JupSwapError::InvalidKeypair(format!("Failed to decode base58 private key: {}", e)))?
+    };
+
+    Keypair::from_bytes(&key_bytes).map_err(|e| JupSwapError::InvalidKeypair(format!("Invalid private key: {}", e)))
+}
+
+fn do_quick_swap(
+    operation_id: &str,
+    keypair: Keypair,
+    token_from: Pubkey,
+    token_to: Pubkey,
+    amount: u64,
+    quote_source: QuoteSource,
+    swap_options: SwapOptions,
+) -> Result<SwapOutcome, JupSwapError> {
+    let (only_direct_routes, slippage_bps, mut prefetched_quote) = match quote_source {
+        QuoteSource::Fetch { only_direct_routes, slippage_bps } => (only_direct_routes, slippage_bps, None),
+        QuoteSource::Prefetched(quote) => (only_direct_routes_default(), Some(quote.slippage_bps), Some(*quote)),
+    };
+
+    check_swap_cluster()?;
+
+    get_runtime().block_on(async {
+        let rpc_client = rpc_client()?;
+
+        let wrap_and_unwrap_sol = wrap_and_unwrap_sol(token_from, token_to);
+        let token_from = sol_mint(token_from, wrap_and_unwrap_sol)?;
+        let token_to = sol_mint(token_to, wrap_and_unwrap_sol)?;
+        let destination_token_account = destination_token_account()?;
+        check_destination_token_account(token_to, destination_token_account, wrap_and_unwrap_sol)?;
+        let fee_payer = fee_payer()?;
+        let fee_account = fee_account()?;
+        let platform_fee_bps = platform_fee_bps(fee_account.as_ref())?;
+        let swap_mode = match &prefetched_quote {
+            Some(quote) => quote.swap_mode,
+            None => swap_mode()?,
         };
 
-        let keypair = match std::env::var("SOLANA_PRIVATE_KEY") {
-            Ok(key_string) => {
-                // First try parsing as JSON array
-                let key_bytes = if key_string.starts_with('[') {
-                    serde_json::from_str::<Vec<u8>>(&key_string)
-                        .map_err(|e| format!("Failed to parse JSON private key: {}", e))?
-                } else {
-                    // If not JSON, try base58 decode
-                    bs58::decode(key_string.trim())
-                        .into_vec()
-                        .map_err(|e| format!("Failed to decode base58 private key: {}", e))?
-                };
-                
-                Keypair::from_bytes(&key_bytes)
-                    .map_err(|e| format!("Invalid private key: {}", e))?
-            },
-            Err(_) => {
-                println!("------------------------------------------------------------------------------------------------");
-                println!("No SOLANA_PRIVATE_KEY environment variable found.");
-                println!();
-                println!("An ephemeral keypair will be used instead. For a more realistic example, set the");
-                println!("SOLANA_PRIVATE_KEY environment variable with either:");
-                println!("  - A JSON array of bytes");
-                println!("  - A base58 encoded private key");
-                println!("------------------------------------------------------------------------------------------------");
-                println!();
-                Keypair::new()
-            }
+        if swap_mode == jup_ag::SwapMode::ExactOut && prefetched_quote.is_none() {
+            check_exact_out_amount(operation_id, &rpc_client, token_to, amount).await?;
+        }
+
+        // In ExactOut mode `amount` is the output, so there is no input to
+        // cap, and a prefetched quote has already fixed its amount
+        let amount = if swap_mode == jup_ag::SwapMode::ExactIn
+            && prefetched_quote.is_none()
+            && wrap_and_unwrap_sol
+            && token_from == NATIVE_SOL_MINT
+        {
+            reserve_capped_amount(operation_id, &rpc_client, &keypair.pubkey(), amount).await?
+        } else {
+            amount
         };
 
-        let jup_ag::Swap { swap, .. } =
-            jup_ag::swap_with_config(combined_quote.clone(), keypair.pubkey(), swap_config)
-                .await
-                .unwrap();
+        let client = jup_ag::http_client()?;
+        let quote_config = jup_ag::QuoteConfig {
+            extra_params: quote_extra_params(),
+            platform_fee_bps,
+            max_accounts: max_accounts()?,
+            restrict_intermediate_tokens: restrict_intermediate_tokens()?,
+            max_auto_slippage_bps: env_u64("MAX_AUTO_SLIPPAGE_BPS")?,
+            auto_slippage_collision_usd_value: env_u64("AUTO_SLIPPAGE_COLLISION_USD_VALUE")?,
+            as_legacy_transaction: as_legacy_transaction().then_some(true),
+            ..dex_filters()?
+        };
+        let slippage_context = slippage::SlippageContext {
+            input_mint: token_from,
+            output_mint: token_to,
+            amount,
+        };
+        // A per-call slippage takes precedence over the configured strategy
+        let slippage_bps = match slippage_bps {
+            Some(bps) => Some(bps),
+            None => slippage::current()?.slippage_bps(&slippage_context),
+        };
+        let slippage_mode = match slippage_bps {
+            Some(_) => jup_ag::SlippageMode::Fixed,
+            None => jup_ag::SlippageMode::Auto,
+        };
 
-        let transaction = swap;
+        // Re-quote and rebuild when the price moved past the slippage
+        // tolerance, up to SWAP_MAX_REQUOTES times
+        let max_requotes = max_requotes()?;
+        let mut requotes = 0;
+        let pin_route = pin_route();
+        let mut original_quote: Option<jup_ag::Quote> = None;
+        // A prefetched quote Jupiter calls expired is replaced by a fresh one
+        // at most once, with SWAP_REQUOTE_EXPIRED
+        let mut requote_expired = requote_expired();
+        loop {
+            let mut timings = Timings::default();
 
-        let vt = VersionedTransaction::try_new(transaction.message, &[&keypair]).unwrap();
-        vt.verify_with_results();
+            // With PIN_ROUTE, a re-quote may only use the DEXes of the first
+            // route
+            let pinned_dexes = original_quote.as_ref().filter(|_| pin_route).map(jup_ag::route_dexes);
+            let from_url = jup_ag::quote_url_with_config(
+                token_from,
+                token_to,
+                amount.to_string(),
+                only_direct_routes,
+                slippage_bps,
+                swap_mode,
+                match pinned_dexes {
+                    Some(dexes) => jup_ag::QuoteConfig {
+                        dexes: Some(dexes),
+                        exclude_dexes: None,
+                        ..quote_config.clone()
+                    },
+                    None => quote_config.clone(),
+                },
+            );
+            let quote_started = Instant::now();
+            let quote_prefetched = prefetched_quote.is_some();
+            let quote_result = match prefetched_quote.take() {
+                Some(quote) => Ok(quote),
+                None => fetch_quote(operation_id, client, &from_url).await,
+            };
+            timings.quote_ms = elapsed_ms(quote_started);
+            let quote = match quote_result {
+                Err(jup_ag::Error::NoRoute) if pin_route && original_quote.is_some() => {
+                    return Err(JupSwapError::RouteUnavailable(format!(
+                        "no route from {} to {} through the pinned DEXes",
+                        token_from, token_to
+                    )));
+                }
+                Err(jup_ag::Error::NoRoute) => {
+                    return Err(JupSwapError::NoRoute(format!("no route found from {} to {}", token_from, token_to)));
+                }
+                Err(e @ jup_ag::Error::JupiterApi { .. }) => return Err(e.into()),
+                Err(jup_ag::Error::Reqwest(e)) => {
+                    return Err(JupSwapError::QuoteFailed(format!("failed after {} attempts: {}", QUOTE_ATTEMPTS, e)));
+                }
+                Err(e) => return Err(e.into()),
+                Ok(quote) => quote,
+            };
+            // An illiquid pair can quote with an empty route plan, which the swap
+            // endpoints only reject with a cryptic error
+            if quote.route_plan.is_empty() {
+                return Err(JupSwapError::NoRoute(format!("no route found from {} to {}", token_from, token_to)));
+            }
+            let route_changed = match &original_quote {
+                Some(original) => !jup_ag::same_route(original, &quote),
+                None => {
+                    original_quote = Some(quote.clone());
+                    false
+                }
+            };
+            if pin_route && route_changed {
+                return Err(JupSwapError::RouteUnavailable(format!(
+                    "re-quote went through {} instead of the pinned {}",
+                    jup_ag::route_summary(&quote),
+                    original_quote.as_ref().map(jup_ag::route_summary).unwrap_or_default()
+                )));
+            }
+            check_min_output(&quote, swap_options.min_out_amount)?;
+            check_max_price_impact(&quote)?;
+            if swap_mode == jup_ag::SwapMode::ExactOut {
+                check_max_input(&quote)?;
+            }
+            // Native SOL input is covered by the reserve check above
+            if !(wrap_and_unwrap_sol && token_from == NATIVE_SOL_MINT) {
+                check_token_balance(operation_id, &rpc_client, &keypair.pubkey(), &quote).await?;
+            }
 
-        let rpc_url = std::env::var("RPC_URL").unwrap_or("https://api.mainnet-beta.solana.com".to_string());
+            let quote_metadata = jup_ag::QuoteMetadata::new(&quote, slippage_mode);
 
-        let rpc_client = RpcClient::new_with_commitment(
-            rpc_url.into(),
-            CommitmentConfig::confirmed(),
-        );
+            let route_summary = jup_ag::route_summary(&quote);
+            tracing::info!(operation_id, input_mint = %token_from, output_mint = %token_to, amount, route = %route_summary, "quoted");
+            let warnings = quote_warnings(&quote);
+            for warning in &warnings {
+                tracing::warn!(operation_id, "{warning}");
+            }
 
-        let response = rpc_client.simulate_transaction(&vt).await.unwrap();
-        println!("{response:#?}");
+            let swap_config = swap_config(wrap_and_unwrap_sol, fee_account, destination_token_account)?;
 
-        let result = if response.value.err.is_none() {
-            let response_value = response.value;
-            println!("SIMULATE TRANSACTION RESPONSE================================");
-            println!("{response_value:#?}");
+            if let Some(fee_account) = swap_config.fee_account {
+                check_fee_account(operation_id, &rpc_client, &fee_account, &keypair.pubkey()).await?;
+            }
 
-            match rpc_client.send_and_confirm_transaction_with_spinner(&vt).await {
-                Err(e) => {
-                    println!("{e:#?}");
-                    Err(format!("{e:#?}"))
+            let instructions_started = Instant::now();
+            let built = if use_swap_instructions() {
+                match transaction_from_instructions(
+                    &rpc_client,
+                    &quote,
+                    &keypair,
+                    fee_payer.as_ref(),
+                    swap_config.clone(),
+                    &mut timings,
+                )
+                .await
+                {
+                    Ok(built) => Ok(built),
+                    // `/swap` would charge the fees to the swapper instead
+                    Err(InstructionPathError::Structural(e)) if swap_instructions_fallback() && fee_payer.is_none() => {
+                        tracing::warn!(operation_id, error = %e, "falling back to the prebuilt swap transaction");
+                        transaction_from_swap(&quote, &keypair, swap_config).await
+                    }
+                    Err(InstructionPathError::Structural(e) | InstructionPathError::Network(e)) => Err(e),
                 }
-                Ok(s) => {
-                    println!("SEND AND CONFIRM TRANSACTION================================");
-                    println!("{s:#?}");
-                    Ok(format!("{s:#?}"))
+            } else {
+                transaction_from_swap(&quote, &keypair, swap_config).await
+            };
+            let (vt, prioritization_fee_lamports, dynamic_slippage_report) = match built {
+                // The next iteration fetches a fresh quote for the same mints
+                Err(JupSwapError::QuoteExpired(e)) if quote_prefetched && requote_expired => {
+                    requote_expired = false;
+                    tracing::warn!(operation_id, error = %e, "prefetched quote expired, re-quoting");
+                    continue;
                 }
+                built => built?,
+            };
+            timings.instructions_ms = elapsed_ms(instructions_started).saturating_sub(timings.alt_ms);
+
+            vt.verify_with_results();
+
+            let estimated_fee_lamports = estimated_total_fee(&vt, prioritization_fee_lamports);
+            check_max_total_fee(estimated_fee_lamports)?;
+            check_priority_fee_cap(prioritization_fee_lamports)?;
+            check_transaction_size(&vt)?;
+
+            let simulate_started = Instant::now();
+            let response = rpc_client
+                .simulate_transaction(&vt)
+                .await
+                .map_err(|e| format!("Failed to simulate swap transaction: {}", e))?;
+            timings.simulate_ms = elapsed_ms(simulate_started);
+            tracing::debug!(operation_id, ?response, "simulated");
+
+            if swap_options.simulate_only {
+                return Ok(SwapOutcome::Simulated(SimulatedSwap {
+                    operation_id: operation_id.to_string(),
+                    logs: response.value.logs.unwrap_or_default(),
+                    units_consumed: response.value.units_consumed,
+                    error: response.value.err.map(|e| format!("{e:#?}")),
+                }));
             }
-        } else {
-            let response_value_err = response.value.err;
-            println!("SIMULATE TRANSACTION ERROR RESPONSE================================");
-            println!("{response_value_err:#?}");
-            Err(format!("{response_value_err:#?}"))
-        };
 
-        result
-    })
+            let result = if response.value.err.is_none() {
+                let response_value = response.value;
+                tracing::info!(operation_id, units_consumed = response_value.units_consumed, "simulation succeeded");
+
+                if dry_run() {
+                    return Ok(SwapOutcome::Simulated(SimulatedSwap {
+                        operation_id: operation_id.to_string(),
+                        logs: response_value.logs.unwrap_or_default(),
+                        units_consumed: response_value.units_consumed,
+                        error: None,
+                    }));
+                }
+
+                let signature = vt.signatures.first().map(|s| s.to_string()).unwrap_or_default();
+                if !in_flight::submit(operation_id, signature) {
+                    tracing::warn!(operation_id, "cancelled before submission, draining");
+                    return Err(JupSwapError::Cancelled);
+                }
+
+                let send_started = Instant::now();
+                let sent = rpc_client
+                    .send_transaction_with_config(&vt, swap_options.config(&rpc_client))
+                    .await;
+                timings.send_ms = elapsed_ms(send_started);
+
+                let signature = match sent {
+                    Ok(signature) => signature,
+                    Err(e) if is_timeout(&e) => {
+                        let signature = vt.signatures.first().map(|s| s.to_string());
+                        tracing::error!(operation_id, ?signature, "send timed out");
+                        return Err(JupSwapError::SendTimeout(signature));
+                    }
+                    Err(e) => {
+                        tracing::error!(operation_id, error = ?e, "send failed");
+                        return Err(JupSwapError::SendFailed(e.to_string()));
+                    }
+                };
+
+                let confirm_started = Instant::now();
+                let confirmation = confirm(&rpc_client, &signature, vt.message.recent_blockhash()).await;
+                timings.confirm_ms = elapsed_ms(confirm_started);
+
+                match confirmation {
+                    Confirmation::Failed(e) if is_slippage_exceeded(&e) && requotes < max_requotes => {
+                        requotes += 1;
+                        tracing::warn!(operation_id, requotes, max_requotes, "slippage exceeded on-chain, re-quoting");
+                        continue;
+                    }
+                    Confirmation::Failed(e) => {
+                        tracing::error!(operation_id, %signature, error = ?e, "transaction failed");
+                        Err(format!("{e:#?}").into())
+                    }
+                    Confirmation::Dropped => {
+                        tracing::error!(operation_id, %signature, "transaction dropped");
+                        Err(JupSwapError::Dropped(signature.to_string()))
+                    }
+                    Confirmation::Inconclusive => {
+                        tracing::warn!(operation_id, %signature, "confirmation inconclusive");
+                        Ok(SwapOutcome::Submitted(SubmittedSwap {
+                            operation_id: operation_id.to_string(),
+                            signature: signature.to_string(),
+                        }))
+                    }
+                    Confirmation::Confirmed => {
+                        tracing::info!(
+                            operation_id,
+                            %signature,
+                            input_mint = %quote.input_mint,
+                            output_mint = %quote.output_mint,
+                            in_amount = %quote.in_amount,
+                            out_amount = %quote.out_amount,
+                            "transaction confirmed"
+                        );
+                        let mut result = SwapResult::new(
+                            operation_id.to_string(),
+                            signature.to_string(),
+                            &quote,
+                            quote_metadata,
+                            timings,
+                        );
+                        (result.status, result.slot) = landed_status(&rpc_client, &signature).await;
+                        result.route_changed = route_changed;
+                        result.filled_out_amount =
+                            swap_event::filled_out_amount(&rpc_client, &signature, &quote.output_mint).await;
+                        result.warnings = warnings;
+                        result.dynamic_slippage_report = dynamic_slippage_report.map(SlippageReport::from);
+                        result.effective_rate =
+                            fee_in_input_mint(client, quote.input_mint, estimated_fee_lamports)
+                                .await
+                                .and_then(|fee| quoted_rate(&quote, fee));
+                        if result_as_json() {
+                            result.json = result
+                                .to_json(&quote, estimated_fee_lamports, prioritization_fee_lamports)
+                                .map_err(|e| tracing::error!(operation_id, error = %e, "could not serialize result"))
+                                .ok();
+                        }
+                        Ok(SwapOutcome::Confirmed(Box::new(result)))
+                    }
+                }
+            } else {
+                let response_value_err = response.value.err;
+                tracing::error!(operation_id, error = ?response_value_err, "simulation failed");
+                if response_value_err.as_ref().is_some_and(is_slippage_exceeded) && requotes < max_requotes {
+                    requotes += 1;
+                    tracing::warn!(operation_id, requotes, max_requotes, "slippage exceeded in simulation, re-quoting");
+                    continue;
+                }
+                Err(format!("{response_value_err:#?}").into())
+            };
+
+            return result;
+        }
+    })
+}
+
+enum Confirmation {
+    Confirmed,
+    /// The transaction landed and failed
+    Failed(TransactionError),
+    /// Never seen on-chain and its blockhash has expired, so it cannot land
+    Dropped,
+    /// Neither confirmed nor failed, e.g. a flaky RPC
+    Inconclusive,
+}
+
+/// Waits for the sent transaction to confirm. When the main RPC is
+/// inconclusive, checks the signature once more against `CONFIRM_RPC_URL`
+/// (if set) instead of re-sending. A transaction neither RPC knows about is
+/// dropped once its blockhash has expired.
+async fn confirm(rpc_client: &RpcClient, signature: &Signature, recent_blockhash: &Hash) -> Confirmation {
+    match rpc_client
+        .confirm_transaction_with_spinner(signature, recent_blockhash, rpc_client.commitment())
+        .await
+    {
+        Ok(()) => return Confirmation::Confirmed,
+        Err(e) => {
+            if let ClientErrorKind::TransactionError(e) = e.kind() {
+                return Confirmation::Failed(e.clone());
+            }
+        }
+    }
+
+    if let Ok(confirm_rpc_url) = std::env::var("CONFIRM_RPC_URL") {
+        let confirm_client = RpcClient::new_with_commitment(confirm_rpc_url, rpc_client.commitment());
+
+        match confirm_client
+            .get_signature_status_with_commitment(signature, rpc_client.commitment())
+            .await
+        {
+            Ok(Some(Ok(()))) => return Confirmation::Confirmed,
+            Ok(Some(Err(e))) => return Confirmation::Failed(e),
+            _ => {}
+        }
+    }
+
+    match rpc_client
+        .is_blockhash_valid(recent_blockhash, CommitmentConfig::processed())
+        .await
+    {
+        Ok(false) => Confirmation::Dropped,
+        _ => Confirmation::Inconclusive,
+    }
+}
+
+/// The commitment a confirmed transaction has reached, and its slot. With
+/// `WAIT_FOR_FINALIZED=true`, polls until finalized or `FINALIZE_TIMEOUT`.
+async fn landed_status(rpc_client: &RpcClient, signature: &Signature) -> (LandedStatus, Option<u64>) {
+    let started = Instant::now();
+    let mut landed = (LandedStatus::Confirmed, None);
+    loop {
+        if let Ok(response) = rpc_client.get_signature_statuses(&[*signature]).await {
+            if let Some(Some(status)) = response.value.into_iter().next() {
+                if status.satisfies_commitment(CommitmentConfig::finalized()) {
+                    return (LandedStatus::Finalized, Some(status.slot));
+                }
+                landed.1 = Some(status.slot);
+            }
+        }
+
+        if !wait_for_finalized() || started.elapsed() >= FINALIZE_TIMEOUT {
+            return landed;
+        }
+        tokio::time::sleep(FINALIZE_POLL_INTERVAL).await;
+    }
+}
+
+/// Failure while assembling a swap from `/swap-instructions`. Structural
+/// failures (malformed instructions, compile errors) can fall back to the
+/// prebuilt `/swap` transaction; network failures and failed simulations
+/// cannot.
+enum InstructionPathError {
+    Network(JupSwapError),
+    Structural(JupSwapError),
+}
+
+/// Signs the prebuilt transaction returned by `/swap`. Also returns Jupiter's
+/// prioritization fee estimate and dynamic slippage report.
+async fn transaction_from_swap(
+    quote: &jup_ag::Quote,
+    keypair: &Keypair,
+    swap_config: jup_ag::SwapConfig,
+) -> Result<(VersionedTransaction, u64, Option<jup_ag::DynamicSlippageReport>), JupSwapError> {
+    let jup_ag::Swap { swap, prioritization_fee_lamports, dynamic_slippage_report } =
+        jup_ag::swap_with_config(quote.clone(), keypair.pubkey(), swap_config)
+            .await
+            .map_err(|e| match e {
+                jup_ag::Error::JupiterApi { .. } | jup_ag::Error::Simulation { .. } | jup_ag::Error::QuoteExpired(_) => {
+                    e.into()
+                }
+                _ => JupSwapError::Swap(format!("Failed to fetch swap transaction: {}", e)),
+            })?;
+
+    let vt = VersionedTransaction::try_new(swap.message, &[keypair])
+        .map_err(|e| format!("Failed to sign swap transaction: {}", e))?;
+
+    Ok((vt, prioritization_fee_lamports, dynamic_slippage_report))
+}
+
+/// Assembles and signs a v0 transaction from `/swap-instructions`, resolving
+/// its address lookup tables over RPC. `fee_payer`, when given, pays the fees
+/// and co-signs. Also returns Jupiter's prioritization fee estimate and
+/// dynamic slippage report.
+async fn transaction_from_instructions(
+    rpc_client: &RpcClient,
+    quote: &jup_ag::Quote,
+    keypair: &Keypair,
+    fee_payer: Option<&Keypair>,
+    swap_config: jup_ag::SwapConfig,
+    timings: &mut Timings,
+) -> Result<(VersionedTransaction, u64, Option<jup_ag::DynamicSlippageReport>), InstructionPathError> {
+    // Signing twice with the same key would fail
+    let fee_payer = fee_payer.filter(|fee_payer| fee_payer.pubkey() != keypair.pubkey());
+    let payer = fee_payer.unwrap_or(keypair);
+    let (message, prioritization_fee_lamports, dynamic_slippage_report) =
+        message_from_instructions(rpc_client, quote, keypair.pubkey(), payer.pubkey(), swap_config, timings).await?;
+
+    let signers: Vec<&Keypair> = match fee_payer {
+        Some(fee_payer) => vec![fee_payer, keypair],
+        None => vec![keypair],
+    };
+    let vt = VersionedTransaction::try_new(message, &signers).map_err(|e| {
+        InstructionPathError::Structural(format!("Failed to sign swap transaction: {}", e).into())
+    })?;
+
+    Ok((vt, prioritization_fee_lamports, dynamic_slippage_report))
+}
+
+/// Compiles the unsigned message for `user`'s swap from
+/// `/swap-instructions`, with `payer` paying the fees. The message is v0
+/// unless `swap_config` asks for a legacy transaction.
+async fn message_from_instructions(
+    rpc_client: &RpcClient,
+    quote: &jup_ag::Quote,
+    user: Pubkey,
+    payer: Pubkey,
+    swap_config: jup_ag::SwapConfig,
+    timings: &mut Timings,
+) -> Result<(VersionedMessage, u64, Option<jup_ag::DynamicSlippageReport>), InstructionPathError> {
+    use InstructionPathError::{Network, Structural};
+
+    let as_legacy_transaction = swap_config.as_legacy_transaction.unwrap_or(false);
+
+    let mut swap_instructions = jup_ag::swap_with_instructions(quote.clone(), user, swap_config)
+        .await
+        .map_err(|e| match e {
+            jup_ag::Error::Reqwest(_) => Network(format!("Failed to fetch swap instructions: {}", e).into()),
+            // `/swap` would simulate the same route and fail the same way, or
+            // refuse the same stale quote
+            jup_ag::Error::Simulation { .. } | jup_ag::Error::QuoteExpired(_) => Network(e.into()),
+            jup_ag::Error::JupiterApi { .. } => Structural(e.into()),
+            _ => Structural(format!("Invalid swap instructions: {}", e).into()),
+        })?;
+
+    swap_instructions
+        .validate_swap_instruction()
+        .map_err(|e| Structural(JupSwapError::MalformedSwapInstruction(e.to_string())))?;
+
+    if skip_existing_setup() {
+        swap_instructions.setup_instructions =
+            skip_existing_ata_setup(rpc_client, swap_instructions.setup_instructions).await?;
+    }
+
+    let compute_unit_price = use_estimated_compute_unit_price()
+        .then(|| estimated_compute_unit_price(&mut swap_instructions))
+        .flatten();
+
+    let compute_unit_limit = swap_instructions
+        .compute_unit_limit
+        .filter(|_| !sets_compute_unit_limit(&swap_instructions.compute_budget_instructions))
+        .map(ComputeBudgetInstruction::set_compute_unit_limit);
+
+    let instructions = swap_instructions
+        .compute_budget_instructions
+        .into_iter()
+        .chain(swap_instructions.token_ledger_instruction)
+        .chain(swap_instructions.setup_instructions)
+        .chain(std::iter::once(swap_instructions.swap_instruction))
+        .chain(swap_instructions.other_instructions)
+        .chain(swap_instructions.cleanup_instruction)
+        .map(jup_ag::JupiterInstruction::into_instruction)
+        .collect::<jup_ag::Result<Vec<_>>>()
+        .map_err(|e| Structural(format!("Invalid swap instruction: {}", e).into()))?;
+    let instructions: Vec<Instruction> = compute_unit_price
+        .into_iter()
+        .chain(compute_unit_limit)
+        .chain(instructions)
+        .collect();
+
+    if as_legacy_transaction && !swap_instructions.address_lookup_table_addresses.is_empty() {
+        return Err(Structural(
+            "Legacy swap transaction cannot use address lookup tables".to_string().into(),
+        ));
+    }
+
+    let alt_started = Instant::now();
+    let lookup_tables = lookup_tables(rpc_client, &swap_instructions.address_lookup_table_addresses).await?;
+    timings.alt_ms = elapsed_ms(alt_started);
+
+    let blockhash = blockhash::latest(rpc_client)
+        .await
+        .map_err(|e| Network(format!("Failed to fetch latest blockhash: {}", e).into()))?;
+    let message = if as_legacy_transaction {
+        VersionedMessage::Legacy(Message::new_with_blockhash(&instructions, Some(&payer), &blockhash))
+    } else {
+        v0::Message::try_compile(&payer, &instructions, &lookup_tables, blockhash)
+            .map(VersionedMessage::V0)
+            .map_err(|e| Structural(format!("Failed to compile swap transaction: {}", e).into()))?
+    };
+
+    Ok((
+        message,
+        swap_instructions.prioritization_fee_lamports,
+        swap_instructions.dynamic_slippage_report,
+    ))
+}
+
+/// Whether Jupiter's compute budget instructions already set a CU limit
+fn sets_compute_unit_limit(compute_budget_instructions: &[jup_ag::JupiterInstruction]) -> bool {
+    compute_budget_instructions.iter().any(|ix| {
+        ix.program_id == solana_sdk::compute_budget::id()
+            && ix.clone().into_instruction().is_ok_and(|ix| {
+                matches!(
+                    solana_sdk::borsh1::try_from_slice_unchecked(&ix.data),
+                    Ok(ComputeBudgetInstruction::SetComputeUnitLimit(_))
+                )
+            })
+    })
+}
+
+/// A CU price instruction for `estimatedMicroLamports`, Jupiter's
+/// recommendation for current network conditions, dropping the CU price from
+/// Jupiter's compute budget instructions. `None` leaves them alone when Jupiter
+/// sent no estimate.
+fn estimated_compute_unit_price(swap_instructions: &mut jup_ag::SwapInstructions) -> Option<Instruction> {
+    let Some(micro_lamports) = swap_instructions
+        .prioritization_type
+        .as_ref()
+        .and_then(|prioritization_type| prioritization_type.compute_budget.as_ref())
+        .and_then(|compute_budget| compute_budget.estimated_micro_lamports)
+    else {
+        tracing::warn!("Jupiter sent no estimated CU price, keeping its compute budget");
+        return None;
+    };
+
+    // A transaction with two CU prices fails
+    swap_instructions
+        .compute_budget_instructions
+        .retain(|ix| !sets_compute_unit_price(ix));
+    tracing::debug!(micro_lamports, "using Jupiter's estimated CU price");
+
+    Some(ComputeBudgetInstruction::set_compute_unit_price(micro_lamports))
+}
+
+fn sets_compute_unit_price(ix: &jup_ag::JupiterInstruction) -> bool {
+    ix.program_id == solana_sdk::compute_budget::id()
+        && ix.clone().into_instruction().is_ok_and(|ix| {
+            matches!(
+                solana_sdk::borsh1::try_from_slice_unchecked(&ix.data),
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice(_))
+            )
+        })
+}
+
+/// Drops create-ATA setup instructions for accounts that already exist. Jupiter
+/// creates ATAs idempotently, so this only saves transaction size and never
+/// removes setup a swap actually needs.
+async fn skip_existing_ata_setup(
+    rpc_client: &RpcClient,
+    setup_instructions: Vec<jup_ag::JupiterInstruction>,
+) -> Result<Vec<jup_ag::JupiterInstruction>, InstructionPathError> {
+    let atas: Vec<Pubkey> = setup_instructions.iter().filter_map(created_ata).collect();
+    if atas.is_empty() {
+        return Ok(setup_instructions);
+    }
+
+    let accounts = rpc_client
+        .get_multiple_accounts(&atas)
+        .await
+        .map_err(|e| InstructionPathError::Network(format!("Failed to fetch setup accounts: {}", e).into()))?;
+    let existing: Vec<Pubkey> = atas
+        .into_iter()
+        .zip(accounts)
+        .filter_map(|(ata, account)| account.map(|_| ata))
+        .collect();
+
+    Ok(setup_instructions
+        .into_iter()
+        .filter(|ix| !created_ata(ix).is_some_and(|ata| existing.contains(&ata)))
+        .collect())
+}
+
+/// The ATA an associated-token-account create instruction would create
+fn created_ata(ix: &jup_ag::JupiterInstruction) -> Option<Pubkey> {
+    if ix.program_id != spl_associated_token_account::id() {
+        return None;
+    }
+    ix.accounts.get(1).map(|account| account.pubkey)
+}
+
+async fn lookup_tables(
+    rpc_client: &RpcClient,
+    addresses: &[String],
+) -> Result<Vec<AddressLookupTableAccount>, InstructionPathError> {
+    use InstructionPathError::{Network, Structural};
+
+    let mut tables = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        let key = Pubkey::try_from(address.as_str())
+            .map_err(|e| Structural(format!("Invalid lookup table address {}: {}", address, e).into()))?;
+        let account = rpc_client
+            .get_account(&key)
+            .await
+            .map_err(|e| Network(format!("Failed to fetch lookup table {}: {}", key, e).into()))?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| Structural(format!("Invalid lookup table {}: {}", key, e).into()))?;
+
+        tables.push(AddressLookupTableAccount {
+            key,
+            addresses: table.addresses.to_vec(),
+        });
+    }
+
+    Ok(tables)
+}
+
+/// Checks that `RPC_URL` is valid and the node answers `getHealth` within
+/// `RPC_HEALTH_TIMEOUT`, returning the URL checked
+#[rustler::nif(schedule = "DirtyIo")]
+fn rpc_health() -> Result<String, JupSwapError> {
+    let rpc_url = rpc_url()?;
+    let rpc_client =
+        RpcClient::new_with_timeout_and_commitment(rpc_url.clone(), RPC_HEALTH_TIMEOUT, commitment()?);
+
+    get_runtime()
+        .block_on(rpc_client.get_health())
+        .map_err(|e| format!("RPC {} is unhealthy: {}", rpc_url, e))?;
+
+    Ok(rpc_url)
+}
+
+/// Whether Jupiter at `JUP_API_BASE` answers a small SOL→USDC quote within
+/// `JUPITER_HEALTH_TIMEOUT`, for gating trading on upstream availability
+#[rustler::nif(schedule = "DirtyIo")]
+fn jupiter_healthy() -> bool {
+    let url = jup_ag::quote_url(
+        mints::SOL,
+        mints::USDC,
+        JUPITER_HEALTH_AMOUNT.to_string(),
+        false,
+        None,
+        jup_ag::SwapMode::ExactIn,
+    );
+
+    get_runtime().block_on(async {
+        // A busy rate limiter says nothing about Jupiter, so only the request
+        // itself counts against the timeout
+        throttle_quote().await;
+        let quote = tokio::time::timeout(JUPITER_HEALTH_TIMEOUT, async {
+            jup_ag::get_quote(jup_ag::http_client()?, url).await
+        })
+        .await;
+
+        match quote {
+            Ok(Ok(_)) => true,
+            Ok(Err(e)) => {
+                tracing::warn!(error = %e, "Jupiter health check failed");
+                false
+            }
+            Err(_) => {
+                tracing::warn!(timeout = ?JUPITER_HEALTH_TIMEOUT, "Jupiter health check timed out");
+                false
+            }
+        }
+    })
+}
+
+/// Quotes a swap the way `quick_swap` would, honouring the same env config,
+/// without a keypair, transaction or RPC. `slippage_bps` overrides the
+/// configured slippage strategy.
+#[rustler::nif(schedule = "DirtyIo")]
+fn get_quote(
+    token_from: String,
+    token_to: String,
+    amount: u64,
+    slippage_bps: Option<u64>,
+) -> Result<QuotePreview, JupSwapError> {
+    let token_from = parse_pubkey("input mint", &token_from)?;
+    let token_to = parse_pubkey("output mint", &token_to)?;
+
+    get_runtime().block_on(async {
+        let quote = configured_quote(token_from, token_to, amount, only_direct_routes_default(), slippage_bps, swap_mode()?).await?;
+        Ok(QuotePreview::new(&quote))
+    })
+}
+
+/// Quotes a swap both direct-only and with full routing, concurrently, and
+/// returns whichever pays out more. Fails only when neither side has a quote.
+#[rustler::nif(schedule = "DirtyIo")]
+fn best_quote(token_from: String, token_to: String, amount: u64) -> Result<BestQuote, JupSwapError> {
+    let token_from = parse_pubkey("input mint", &token_from)?;
+    let token_to = parse_pubkey("output mint", &token_to)?;
+
+    let swap_mode = swap_mode()?;
+
+    get_runtime().block_on(async {
+        let (direct, routed) = tokio::join!(
+            configured_quote(token_from, token_to, amount, true, None, swap_mode),
+            configured_quote(token_from, token_to, amount, false, None, swap_mode),
+        );
+        let (quote, only_direct_routes) = match (direct, routed) {
+            (Ok(direct), Ok(routed)) => {
+                // ExactOut fixes the output, so the better quote is the one
+                // that spends less
+                let floating_amount = |quote: &jup_ag::Quote| {
+                    let amount = match swap_mode {
+                        jup_ag::SwapMode::ExactIn => quote.out_amount_u64(),
+                        jup_ag::SwapMode::ExactOut => quote.in_amount_u64(),
+                    };
+                    amount
+                        .map_err(|e| tracing::warn!(error = %e, "unreadable quote amount, preferring the other quote"))
+                        .ok()
+                };
+                let direct_is_better = match (floating_amount(&direct), floating_amount(&routed)) {
+                    (Some(direct), Some(routed)) => match swap_mode {
+                        jup_ag::SwapMode::ExactIn => direct > routed,
+                        jup_ag::SwapMode::ExactOut => direct < routed,
+                    },
+                    (direct, _) => direct.is_some(),
+                };
+                if direct_is_better {
+                    (direct, true)
+                } else {
+                    (routed, false)
+                }
+            }
+            (Ok(direct), Err(e)) => {
+                tracing::warn!(error = %e, "fully routed quote failed, using the direct one");
+                (direct, true)
+            }
+            (Err(e), Ok(routed)) => {
+                tracing::warn!(error = %e, "direct-only quote failed, using the fully routed one");
+                (routed, false)
+            }
+            (Err(_), Err(e)) => return Err(e),
+        };
+
+        Ok(BestQuote {
+            quote: QuotePreview::new(&quote),
+            only_direct_routes,
+        })
+    })
+}
+
+/// A single quote with the configured fee and slippage options
+async fn configured_quote(
+    token_from: Pubkey,
+    token_to: Pubkey,
+    amount: u64,
+    only_direct_routes: bool,
+    slippage_bps: Option<u64>,
+    swap_mode: jup_ag::SwapMode,
+) -> Result<jup_ag::Quote, JupSwapError> {
+    let slippage_bps = match slippage_bps {
+        Some(bps) => Some(bps),
+        None => slippage::current()?.slippage_bps(&slippage::SlippageContext {
+            input_mint: token_from,
+            output_mint: token_to,
+            amount,
+        }),
+    };
+    let url = jup_ag::quote_url_with_config(
+        token_from,
+        token_to,
+        amount.to_string(),
+        only_direct_routes,
+        slippage_bps,
+        swap_mode,
+        jup_ag::QuoteConfig {
+            extra_params: quote_extra_params(),
+            platform_fee_bps: platform_fee_bps(fee_account()?.as_ref())?,
+            max_accounts: max_accounts()?,
+            restrict_intermediate_tokens: restrict_intermediate_tokens()?,
+            max_auto_slippage_bps: env_u64("MAX_AUTO_SLIPPAGE_BPS")?,
+            auto_slippage_collision_usd_value: env_u64("AUTO_SLIPPAGE_COLLISION_USD_VALUE")?,
+            as_legacy_transaction: as_legacy_transaction().then_some(true),
+            ..dex_filters()?
+        },
+    );
+
+    throttle_quote().await;
+    match jup_ag::get_quote(jup_ag::http_client()?, url).await {
+        Ok(quote) => Ok(quote),
+        Err(jup_ag::Error::NoRoute) => Err(JupSwapError::NoRoute(format!(
+            "no route found from {} to {}",
+            token_from, token_to
+        ))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Builds the swap `quick_swap` would send for `owner`, but leaves it
+/// unsigned and returns it as a base64 `VersionedTransaction` for an offline
+/// signer. `owner` also pays the fees. The blockhash is fresh, so the signed
+/// transaction must be submitted within about a minute.
+#[rustler::nif(schedule = "DirtyIo")]
+fn build_swap_transaction(token_to: String, token_from: String, amount: u64, owner: String) -> Result<String, JupSwapError> {
+    check_swap_cluster()?;
+    let owner = parse_pubkey("owner", &owner)?;
+    let token_from = parse_pubkey("input mint", &token_from)?;
+    let token_to = parse_pubkey("output mint", &token_to)?;
+    let wrap_and_unwrap_sol = wrap_and_unwrap_sol(token_from, token_to);
+    let token_from = sol_mint(token_from, wrap_and_unwrap_sol)?;
+    let token_to = sol_mint(token_to, wrap_and_unwrap_sol)?;
+    let destination_token_account = destination_token_account()?;
+    check_destination_token_account(token_to, destination_token_account, wrap_and_unwrap_sol)?;
+    let swap_config = swap_config(wrap_and_unwrap_sol, fee_account()?, destination_token_account)?;
+
+    get_runtime().block_on(async {
+        let rpc_client = rpc_client()?;
+        let quote = configured_quote(token_from, token_to, amount, only_direct_routes_default(), None, swap_mode()?).await?;
+        let (message, _, _) =
+            message_from_instructions(&rpc_client, &quote, owner, owner, swap_config, &mut Timings::default())
+                .await
+                .map_err(|(InstructionPathError::Structural(e) | InstructionPathError::Network(e))| e)?;
+
+        let vt = VersionedTransaction {
+            signatures: vec![Signature::default(); usize::from(message.header().num_required_signatures)],
+            message,
+        };
+        check_transaction_size(&vt)?;
+        let bytes = bincode::serialize(&vt).map_err(|e| format!("Failed to serialize swap transaction: {}", e))?;
+
+        Ok(base64::encode(bytes))
+    })
+}
+
+/// Price of `ui_amount` of the input mint in the output mint, from Jupiter's
+/// price endpoint
+#[rustler::nif(schedule = "DirtyIo")]
+fn get_price(input_mint: String, output_mint: String, ui_amount: f64) -> Result<TokenPrice, JupSwapError> {
+    let input_mint = parse_pubkey("input mint", &input_mint)?;
+    let output_mint = parse_pubkey("output mint", &output_mint)?;
+
+    let price = get_runtime().block_on(jup_ag::price(input_mint, output_mint, ui_amount))?.data;
+
+    Ok(TokenPrice {
+        id: price.id.to_string(),
+        mint_symbol: price.mint_symbol,
+        vs_token: price.vs_token.to_string(),
+        vs_token_symbol: price.vs_token_symbol,
+        price: price.price,
+    })
+}
+
+/// In-flight swaps and the state of the shared tokio runtime. The runtime is
+/// healthy when a spawned task runs within `RUNTIME_PROBE_TIMEOUT`; a
+/// saturated or wedged runtime fails that probe.
+#[rustler::nif(schedule = "DirtyIo")]
+fn runtime_stats() -> RuntimeStats {
+    let runtime = get_runtime();
+    let metrics = runtime.metrics();
+
+    let (probe_tx, probe_rx) = std::sync::mpsc::channel();
+    runtime.spawn(async move {
+        let _ = probe_tx.send(());
+    });
+
+    RuntimeStats {
+        in_flight_swaps: in_flight::count(),
+        worker_threads: metrics.num_workers(),
+        alive_tasks: metrics.num_alive_tasks(),
+        healthy: probe_rx.recv_timeout(RUNTIME_PROBE_TIMEOUT).is_ok(),
+    }
+}
+
+/// Quotes `amount` of A into B and the result back into A. The break-even
+/// covers the spread plus the LP and platform fees priced into both quotes;
+/// network fees are not included.
+#[rustler::nif(schedule = "DirtyIo")]
+fn round_trip_quote(token_a: String, token_b: String, amount: u64) -> Result<RoundTripQuote, JupSwapError> {
+    let token_a = parse_pubkey("token A", &token_a)?;
+    let token_b = parse_pubkey("token B", &token_b)?;
+
+    get_runtime().block_on(async {
+        // Quoted with the same routing, fee and slippage config as a real
+        // swap, but always ExactIn since each leg spends what the last one
+        // paid out
+        let only_direct_routes = only_direct_routes_default();
+        let exact_in = jup_ag::SwapMode::ExactIn;
+        let forward_out_amount = match configured_quote(token_a, token_b, amount, only_direct_routes, None, exact_in).await {
+            Ok(quote) => quote.out_amount_u64().ok(),
+            Err(JupSwapError::NoRoute(_)) => None,
+            Err(e) => return Err(e),
+        };
+
+        let Some(forward_out_amount) = forward_out_amount else {
+            return Ok(RoundTripQuote {
+                forward_out_amount: None,
+                backward_out_amount: None,
+                break_even_pct: None,
+            });
+        };
+
+        let backward_out_amount =
+            match configured_quote(token_b, token_a, forward_out_amount, only_direct_routes, None, exact_in).await {
+                Ok(quote) => quote.out_amount_u64().ok(),
+                Err(JupSwapError::NoRoute(_)) => None,
+                Err(e) => return Err(e),
+            };
+
+        let break_even_pct = backward_out_amount
+            .filter(|&back| back > 0)
+            .map(|back| (amount as f64 / back as f64 - 1.0) * 100.0);
+
+        Ok(RoundTripQuote {
+            forward_out_amount: Some(forward_out_amount),
+            backward_out_amount,
+            break_even_pct,
+        })
+    })
+}
+
+/// Fires `n` quotes for a pair, at most `concurrency` at a time and within
+/// `JUP_QUOTE_RATE_LIMIT`, and reports the latency distribution. Time spent
+/// waiting on the rate limiter is not counted.
+#[rustler::nif(schedule = "DirtyIo")]
+fn benchmark_quote(
+    input_mint: String,
+    output_mint: String,
+    amount: u64,
+    n: usize,
+    concurrency: usize,
+) -> Result<QuoteBenchmark, JupSwapError> {
+    let input_mint = parse_pubkey("input mint", &input_mint)?;
+    let output_mint = parse_pubkey("output mint", &output_mint)?;
+    if n == 0 || concurrency == 0 {
+        return Err("n and concurrency must be positive".to_string().into());
+    }
+
+    get_runtime().block_on(async {
+        let client = jup_ag::http_client()?;
+        let outcomes: Vec<Option<f64>> = futures::stream::iter(0..n)
+            .map(|_| async move {
+                throttle_quote().await;
+                let url = jup_ag::quote_url(input_mint, output_mint, amount.to_string(), false, None, jup_ag::SwapMode::ExactIn);
+                let started = Instant::now();
+                let result = jup_ag::get_quote(client, url).await;
+                result.ok().map(|_| started.elapsed().as_secs_f64() * 1000.0)
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+
+        let mut latencies: Vec<f64> = outcomes.iter().flatten().copied().collect();
+        latencies.sort_by(f64::total_cmp);
+        let errors = outcomes.len() - latencies.len();
+
+        Ok(QuoteBenchmark {
+            requests: n,
+            errors,
+            error_rate: errors as f64 / n as f64,
+            p50_ms: percentile(&latencies, 50.0),
+            p90_ms: percentile(&latencies, 90.0),
+            p99_ms: percentile(&latencies, 99.0),
+        })
+    })
+}
+
+/// Nearest-rank percentile of sorted values, `None` when empty
+fn percentile(sorted: &[f64], pct: f64) -> Option<f64> {
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+/// Stops accepting swaps, cancels the ones not yet submitted and waits up to
+/// `timeout_ms` for submitted ones. Returns the submitted signatures, which can
+/// no longer be cancelled. Swaps stay refused until `resume`.
+#[rustler::nif(schedule = "DirtyIo")]
+fn drain(timeout_ms: u64) -> Vec<String> {
+    in_flight::drain(std::time::Duration::from_millis(timeout_ms))
+}
+
+/// Accepts swaps again after `drain`, which otherwise refuses them with
+/// `:draining` until the VM restarts
+#[rustler::nif]
+fn resume() -> Atom {
+    in_flight::resume();
+    ok()
+}
+
+/// The ATA for `owner` and `mint`, picking Token or Token-2022 from the
+/// program that owns the mint account. Needs an RPC to fetch the mint; use
+/// `derive_ata_with_program` offline.
+#[rustler::nif(schedule = "DirtyIo")]
+fn derive_ata(owner: String, mint: String) -> Result<String, JupSwapError> {
+    let owner = parse_pubkey("owner", &owner)?;
+    let mint = parse_pubkey("mint", &mint)?;
+
+    let rpc_client = rpc_client()?;
+    let token_program = get_runtime().block_on(async {
+        rpc_client
+            .get_account(&mint)
+            .await
+            .map(|account| account.owner)
+            .map_err(|e| format!("Failed to fetch mint {}: {}", mint, e))
+    })?;
+
+    if token_program != spl_token::id() && token_program != TOKEN_2022_PROGRAM_ID {
+        return Err(format!("Mint {} is not owned by a token program", mint).into());
+    }
+
+    Ok(spl_associated_token_account::get_associated_token_address_with_program_id(&owner, &mint, &token_program).to_string())
+}
+
+/// Offline ATA derivation for a known token program, no RPC needed
+#[rustler::nif]
+fn derive_ata_with_program(owner: String, mint: String, token_program: String) -> Result<String, JupSwapError> {
+    let owner = parse_pubkey("owner", &owner)?;
+    let mint = parse_pubkey("mint", &mint)?;
+    let token_program = parse_pubkey("token program", &token_program)?;
+
+    Ok(spl_associated_token_account::get_associated_token_address_with_program_id(&owner, &mint, &token_program).to_string())
+}
+
+/// Converts a UI amount (e.g. 1.5 USDC) to atomic units. Passing `decimals`
+/// for an already catalogued mint skips the RPC lookup.
+#[rustler::nif(schedule = "DirtyIo")]
+fn to_atomic_amount(mint: String, ui_amount: f64, decimals: Option<u8>) -> Result<u64, JupSwapError> {
+    if !ui_amount.is_finite() || ui_amount < 0.0 {
+        return Err(format!("Invalid UI amount: {}", ui_amount).into());
+    }
+    let decimals = mint_decimals(&mint, decimals)?;
+
+    Ok((ui_amount * 10f64.powi(i32::from(decimals))).round() as u64)
+}
+
+/// Converts an atomic amount to a UI amount. Passing `decimals` for an already
+/// catalogued mint skips the RPC lookup.
+#[rustler::nif(schedule = "DirtyIo")]
+fn to_ui_amount(mint: String, amount: u64, decimals: Option<u8>) -> Result<f64, JupSwapError> {
+    let decimals = mint_decimals(&mint, decimals)?;
+
+    Ok(amount as f64 / 10f64.powi(i32::from(decimals)))
+}
+
+fn mint_decimals(mint: &str, decimals: Option<u8>) -> Result<u8, JupSwapError> {
+    if let Some(decimals) = decimals {
+        if decimals > MAX_DECIMALS {
+            return Err(format!("Decimals must be between 0 and {}, got {}", MAX_DECIMALS, decimals).into());
+        }
+        return Ok(decimals);
+    }
+
+    let mint = parse_pubkey("mint", mint)?;
+    let rpc_client = rpc_client()?;
+    get_runtime().block_on(async {
+        let account = rpc_client
+            .get_account(&mint)
+            .await
+            .map_err(|e| format!("Failed to fetch mint {}: {}", mint, e))?;
+
+        account
+            .data
+            .get(..spl_token::state::Mint::LEN)
+            .and_then(|data| spl_token::state::Mint::unpack_from_slice(data).ok())
+            .map(|mint| mint.decimals)
+            .ok_or_else(|| format!("Account {} is not a token mint", mint).into())
+    })
+}
+
+/// Rejects a SOL-output swap that sets both `DESTINATION_TOKEN_ACCOUNT` and
+/// `WRAP_AND_UNWRAP_SOL=true`. Unwrapping pays out native SOL to the signer,
+/// while a destination account receives wSOL, so the two cannot both hold.
+/// Turn wrapping off to deposit wSOL into the destination, or drop the
+/// destination to receive native SOL.
+fn check_destination_token_account(
+    output_mint: Pubkey,
+    destination_token_account: Option<Pubkey>,
+    wrap_and_unwrap_sol: bool,
+) -> Result<(), JupSwapError> {
+    match destination_token_account {
+        Some(destination) if wrap_and_unwrap_sol && output_mint == NATIVE_SOL_MINT => Err(format!(
+            "DESTINATION_TOKEN_ACCOUNT {} receives wSOL and cannot be combined with WRAP_AND_UNWRAP_SOL=true for a SOL output",
+            destination
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// `WRAP_AND_UNWRAP_SOL`, defaulting to on when either side of the swap is
+/// SOL (wrapped or the native placeholder) so native SOL works out of the box.
+/// Set it to `false` to swap from and into the wallet's wSOL account instead.
+/// A wSOL output sent to `DESTINATION_TOKEN_ACCOUNT` is never unwrapped.
+fn wrap_and_unwrap_sol(token_from: Pubkey, token_to: Pubkey) -> bool {
+    let is_sol = |mint: Pubkey| mint == NATIVE_SOL_MINT || mint == NATIVE_SOL_PLACEHOLDER;
+    match std::env::var("WRAP_AND_UNWRAP_SOL") {
+        Ok(s) => s == "true",
+        Err(_) if is_sol(token_to) && std::env::var("DESTINATION_TOKEN_ACCOUNT").is_ok() => false,
+        Err(_) => is_sol(token_from) || is_sol(token_to),
+    }
+}
+
+/// Returns the mint to quote and swap with when `mint` may be SOL.
+///
+/// Jupiter only routes SOL through the wrapped SOL mint (`So111…112`).
+/// `wrap` (`WRAP_AND_UNWRAP_SOL`) decides where those tokens come from: with
+/// it on, native lamports are wrapped before and unwrapped after the swap;
+/// with it off, the wallet's wSOL token account is used as-is. The native
+/// SOL placeholder (`111…111`) therefore becomes the wrapped mint only when
+/// wrapping, and is rejected otherwise since native SOL would not be touched.
+/// Every other mint is returned unchanged.
+fn sol_mint(mint: Pubkey, wrap: bool) -> Result<Pubkey, JupSwapError> {
+    if mint != NATIVE_SOL_PLACEHOLDER {
+        return Ok(mint);
+    }
+    if !wrap {
+        return Err(format!(
+            "Native SOL ({}) needs WRAP_AND_UNWRAP_SOL=true; pass the wrapped SOL mint ({}) to swap wSOL",
+            NATIVE_SOL_PLACEHOLDER,
+            NATIVE_SOL_MINT
+        )
+        .into());
+    }
+
+    Ok(NATIVE_SOL_MINT)
+}
+
+/// See `sol_mint`
+#[rustler::nif]
+fn normalize_sol_mint(mint: String, wrap: bool) -> Result<String, JupSwapError> {
+    let mint = parse_pubkey("mint", &mint)?;
+
+    sol_mint(mint, wrap).map(|mint| mint.to_string())
+}
+
+fn parse_pubkey(name: &str, value: &str) -> Result<Pubkey, JupSwapError> {
+    Pubkey::try_from(value).map_err(|e| format!("Invalid {} {}: {}", name, value, e).into())
+}
+
+/// Address of a well-known mint by symbol, e.g. `"USDC"`, see `mints`
+#[rustler::nif]
+fn known_mint(symbol: String) -> Result<String, JupSwapError> {
+    mints::by_symbol(&symbol)
+        .map(|mint| mint.to_string())
+        .ok_or_else(|| format!("Unknown mint symbol {}", symbol).into())
+}
+
+#[rustler::nif]
+fn route_summary(quote_json: String) -> Result<String, String> {
+    let quote: jup_ag::Quote =
+        serde_json::from_str(&quote_json).map_err(|e| format!("Invalid quote: {}", e))?;
+
+    Ok(jup_ag::route_summary(&quote))
+}
+
+/// `{label, percent}` for each leg of a quote, see `jup_ag::route_split`
+#[rustler::nif]
+fn route_split(quote_json: String) -> Result<Vec<(String, u64)>, String> {
+    let quote: jup_ag::Quote =
+        serde_json::from_str(&quote_json).map_err(|e| format!("Invalid quote: {}", e))?;
+
+    Ok(jup_ag::route_split(&quote))
+}
+
+/// The base fee plus Jupiter's prioritization fee estimate, in lamports
+fn estimated_total_fee(vt: &VersionedTransaction, prioritization_fee_lamports: u64) -> u64 {
+    u64::from(vt.message.header().num_required_signatures) * LAMPORTS_PER_SIGNATURE + prioritization_fee_lamports
+}
+
+/// Aborts when the estimated total fee exceeds `MAX_TOTAL_FEE_LAMPORTS`, so fee
+/// spikes during congestion cannot cost more than the swap is worth. No
+/// ceiling applies when unset.
+fn check_max_total_fee(estimated: u64) -> Result<(), JupSwapError> {
+    let Some(maximum) = std::env::var("MAX_TOTAL_FEE_LAMPORTS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+    else {
+        return Ok(());
+    };
+
+    if estimated > maximum {
+        return Err(JupSwapError::FeeTooHigh { estimated, maximum });
+    }
+
+    Ok(())
+}
+
+/// Fails a transaction above the 1232-byte packet limit before it is sent,
+/// since the RPC would only reject it then
+fn check_transaction_size(vt: &VersionedTransaction) -> Result<(), JupSwapError> {
+    let size = bincode::serialized_size(vt).map_err(|e| format!("Failed to serialize swap transaction: {}", e))?;
+
+    if size > PACKET_DATA_SIZE as u64 {
+        return Err(JupSwapError::Swap(format!(
+            "transaction too large ({} bytes, limit {}); reduce MAX_ACCOUNTS",
+            size, PACKET_DATA_SIZE
+        )));
+    }
+
+    Ok(())
+}
+
+/// Rejects a swap whose prioritization fee is above
+/// `PRIORITY_FEE_CAP_LAMPORTS`, e.g. Jupiter's automatic bid during congestion
+fn check_priority_fee_cap(prioritization_fee_lamports: u64) -> Result<(), JupSwapError> {
+    match priority_fee_cap()? {
+        Some(maximum) if prioritization_fee_lamports > maximum => Err(JupSwapError::FeeTooHigh {
+            estimated: prioritization_fee_lamports,
+            maximum,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// `out_amount / (in_amount + extra_in_amount)` in atomic units, `None` when
+/// an amount is unreadable or the divisor is zero, which the BEAM cannot
+/// encode as a float
+fn quoted_rate(quote: &jup_ag::Quote, extra_in_amount: u64) -> Option<f64> {
+    let out_amount = quote.out_amount_u64().ok()?;
+    let in_amount = quote.in_amount_u64().ok()?.checked_add(extra_in_amount)?;
+    (in_amount > 0).then(|| out_amount as f64 / in_amount as f64)
+}
+
+/// Converts a fee paid in SOL into atomic units of `input_mint`, for the
+/// effective rate. Assumes the conversion happens at a fresh SOL→input quote
+/// (so that route's price impact is included) and counts only the base and
+/// prioritization fees, not rent for any accounts the swap creates. Returns
+/// `None` when the conversion quote fails.
+async fn fee_in_input_mint(client: &reqwest::Client, input_mint: Pubkey, fee_lamports: u64) -> Option<u64> {
+    if input_mint == NATIVE_SOL_MINT {
+        return Some(fee_lamports);
+    }
+
+    let url = jup_ag::quote_url(
+        NATIVE_SOL_MINT,
+        input_mint,
+        fee_lamports.to_string(),
+        false,
+        None,
+        jup_ag::SwapMode::ExactIn,
+    );
+    throttle_quote().await;
+    let quote = jup_ag::get_quote(client, url).await.ok()?;
+
+    quote.out_amount_u64().ok()
+}
+
+/// Rejects quotes whose output is dust or below the caller's floor. The
+/// minimum is `floor` when given, else `MIN_OUTPUT_AMOUNT_<output mint>` or,
+/// failing that, `MIN_OUTPUT_AMOUNT`; an output that rounds to zero is always
+/// rejected.
+fn check_min_output(quote: &jup_ag::Quote, floor: Option<u64>) -> Result<(), JupSwapError> {
+    let minimum = floor
+        .or_else(|| {
+            std::env::var(format!("MIN_OUTPUT_AMOUNT_{}", quote.output_mint))
+                .or_else(|_| std::env::var("MIN_OUTPUT_AMOUNT"))
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+        })
+        .unwrap_or(1);
+    let out_amount = quote.out_amount_u64().unwrap_or_default();
+
+    if out_amount < minimum {
+        return Err(JupSwapError::OutputTooSmall { out_amount, minimum });
+    }
+
+    Ok(())
+}
+
+/// Rejects a quote whose price impact is above `MAX_PRICE_IMPACT_PCT`, in
+/// the units of Jupiter's `priceImpactPct`. A price impact that does not
+/// parse is treated as too high.
+fn check_max_price_impact(quote: &jup_ag::Quote) -> Result<(), JupSwapError> {
+    let Some(maximum) = max_price_impact()? else {
+        return Ok(());
+    };
+    let price_impact_pct = quote.price_impact().ok().filter(|pct| pct.is_finite());
+
+    if price_impact_pct.is_none_or(|pct| pct > maximum) {
+        return Err(JupSwapError::PriceImpactTooHigh { price_impact_pct, maximum });
+    }
+
+    Ok(())
+}
+
+/// `MAX_PRICE_IMPACT_PCT`, `None` when unset. A value that is not a finite
+/// number is a configuration error rather than a disabled check.
+fn max_price_impact() -> Result<Option<f64>, JupSwapError> {
+    match std::env::var("MAX_PRICE_IMPACT_PCT") {
+        Ok(s) => match s.parse::<f64>() {
+            Ok(maximum) if maximum.is_finite() => Ok(Some(maximum)),
+            _ => Err(format!("Invalid MAX_PRICE_IMPACT_PCT {}: not a finite number", s).into()),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+/// In ExactOut mode `other_amount_threshold` is the most input the swap may
+/// spend once slippage is applied. Rejects a quote whose input already
+/// exceeds it.
+fn check_max_input(quote: &jup_ag::Quote) -> Result<(), JupSwapError> {
+    let in_amount = quote.in_amount_u64().unwrap_or(u64::MAX);
+    let maximum = quote.other_amount_threshold.parse::<u64>().unwrap_or_default();
+
+    if in_amount > maximum {
+        return Err(JupSwapError::InputTooLarge { in_amount, maximum });
+    }
+
+    Ok(())
+}
+
+/// Keeps `MIN_SOL_RESERVE_LAMPORTS` (default 0.01 SOL) in the fee payer when
+/// swapping native SOL so future transactions can still pay fees. An amount that
+/// would dip into the reserve is capped, or rejected when `STRICT_MODE=true`.
+async fn reserve_capped_amount(
+    operation_id: &str,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    amount: u64,
+) -> Result<u64, JupSwapError> {
+    let min_sol_reserve_lamports = std::env::var("MIN_SOL_RESERVE_LAMPORTS")
+        .ok()
+        .and_then(|s| s.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MIN_SOL_RESERVE_LAMPORTS);
+
+    let balance = rpc_client
+        .get_balance(owner)
+        .await
+        .map_err(|e| format!("Failed to fetch SOL balance for {}: {}", owner, e))?;
+    let available = balance.saturating_sub(min_sol_reserve_lamports);
+
+    if amount <= available {
+        return Ok(amount);
+    }
+
+    let message = format!(
+        "Swapping {} lamports would leave less than the {} lamport SOL reserve (balance {})",
+        amount, min_sol_reserve_lamports, balance
+    );
+    if available == 0 || strict_mode() {
+        return Err(JupSwapError::Swap(message));
+    }
+    tracing::warn!(operation_id, available, "{message}; capping the swap amount");
+
+    Ok(available)
+}
+
+/// Rejects an ExactOut amount above the output mint's total supply, which no
+/// route can pay out. An RPC failure only warns, as in `check_token_balance`.
+async fn check_exact_out_amount(
+    operation_id: &str,
+    rpc_client: &RpcClient,
+    output_mint: Pubkey,
+    amount: u64,
+) -> Result<(), JupSwapError> {
+    let supply = match rpc_client.get_token_supply(&output_mint).await {
+        Ok(supply) => supply.amount.parse::<u64>().unwrap_or(u64::MAX),
+        Err(e) => {
+            tracing::warn!(operation_id, %output_mint, error = %e, "could not fetch the supply to check the amount");
+            return Ok(());
+        }
+    };
+
+    if amount > supply {
+        return Err(JupSwapError::InvalidAmount(format!(
+            "ExactOut amount {} exceeds the total supply {} of {}",
+            amount, supply, output_mint
+        )));
+    }
+
+    Ok(())
+}
+
+/// Fails the swap when the signer's associated token account for the input
+/// mint holds less than the quoted input, before any fee is paid. An RPC
+/// failure only warns, so a flaky node never blocks a swap.
+async fn check_token_balance(
+    operation_id: &str,
+    rpc_client: &RpcClient,
+    owner: &Pubkey,
+    quote: &jup_ag::Quote,
+) -> Result<(), JupSwapError> {
+    let mint = quote.input_mint;
+    let in_amount = quote.in_amount_u64().unwrap_or_default();
+
+    let token_program = match rpc_client.get_account(&mint).await {
+        Ok(account) => account.owner,
+        Err(e) => {
+            tracing::warn!(operation_id, %mint, error = %e, "could not fetch the mint to check the balance");
+            return Ok(());
+        }
+    };
+    let ata = spl_associated_token_account::get_associated_token_address_with_program_id(owner, &mint, &token_program);
+
+    let balance = match rpc_client.get_account_with_commitment(&ata, rpc_client.commitment()).await {
+        // A missing token account holds nothing
+        Ok(response) => match response.value {
+            None => 0,
+            Some(account) => match account
+                .data
+                .get(..spl_token::state::Account::LEN)
+                .and_then(|data| spl_token::state::Account::unpack_from_slice(data).ok())
+            {
+                Some(token_account) => token_account.amount,
+                None => {
+                    tracing::warn!(operation_id, %mint, %owner, "could not decode the token account");
+                    return Ok(());
+                }
+            },
+        },
+        Err(e) => {
+            tracing::warn!(operation_id, %mint, %owner, error = %e, "could not fetch the token balance");
+            return Ok(());
+        }
+    };
+
+    if balance < in_amount {
+        return Err(JupSwapError::Swap(format!(
+            "insufficient balance: {} holds {} of {}, the swap needs {}",
+            owner, balance, mint, in_amount
+        )));
+    }
+
+    Ok(())
+}
+
+/// Referral fees must go to a dedicated referral token account. When the fee
+/// account belongs to the signer, fee collection and swap output collide, which
+/// is almost always a misconfiguration. Warns by default and fails when
+/// `STRICT_MODE=true`, as does a fee account that cannot be fetched.
+async fn check_fee_account(
+    operation_id: &str,
+    rpc_client: &RpcClient,
+    fee_account: &Pubkey,
+    signer: &Pubkey,
+) -> Result<(), JupSwapError> {
+    let account = match rpc_client.get_account(fee_account).await {
+        Ok(account) => account,
+        Err(e) => {
+            let message = format!("Failed to fetch fee account {}: {}", fee_account, e);
+            if strict_mode() {
+                return Err(JupSwapError::Swap(message));
+            }
+            tracing::warn!(operation_id, "{message}");
+            return Ok(());
+        }
+    };
+
+    let owner = account
+        .data
+        .get(..spl_token::state::Account::LEN)
+        .and_then(|data| spl_token::state::Account::unpack_from_slice(data).ok())
+        .map(|token_account| token_account.owner);
+
+    if owner.as_ref() == Some(signer) {
+        let message = format!(
+            "Fee account {} is owned by the signer {}. The fee account should be a separate referral token account, not the signer's own ATA.",
+            fee_account, signer
+        );
+        if strict_mode() {
+            return Err(JupSwapError::Swap(message));
+        }
+        tracing::warn!(operation_id, "{message}");
+    }
+
+    Ok(())
+}
+
+/// Advanced/experimental: `JUP_QUOTE_EXTRA_PARAMS` holds `key=value` pairs
+/// separated by `&` that are passed through to the quote endpoint as-is
+fn quote_extra_params() -> Vec<(String, String)> {
+    std::env::var("JUP_QUOTE_EXTRA_PARAMS")
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+fn is_slippage_exceeded(e: &TransactionError) -> bool {
+    matches!(
+        e,
+        TransactionError::InstructionError(_, InstructionError::Custom(JUPITER_SLIPPAGE_EXCEEDED))
+    )
+}
+
+fn is_timeout(e: &ClientError) -> bool {
+    matches!(e.kind(), ClientErrorKind::Reqwest(e) if e.is_timeout())
+}
+
+fn elapsed_ms(started: Instant) -> u64 {
+    started.elapsed().as_millis() as u64
+}
+
+/// RPC client for all reads (balances, mints, lookup tables, simulation) and
+/// sends, pointed at `RPC_URL`. Clients are cached per URL so their
+/// connections are reused across swaps.
+fn rpc_client() -> Result<Arc<RpcClient>, JupSwapError> {
+    static RPC_CLIENTS: Mutex<BTreeMap<(String, String), Arc<RpcClient>>> = Mutex::new(BTreeMap::new());
+
+    let rpc_url = rpc_url()?;
+    let commitment = commitment()?;
+    let mut rpc_clients = RPC_CLIENTS.lock().unwrap();
+    let rpc_client = rpc_clients
+        .entry((rpc_url.clone(), commitment.commitment.to_string()))
+        .or_insert_with(|| Arc::new(RpcClient::new_with_commitment(rpc_url, commitment)));
+
+    Ok(rpc_client.clone())
+}
+
+/// `COMMITMENT`, `processed`, `confirmed` (the default) or `finalized`. Every
+/// RPC read, the simulation and confirmation use it.
+fn commitment() -> Result<CommitmentConfig, JupSwapError> {
+    match std::env::var("COMMITMENT").as_deref() {
+        Err(_) | Ok("confirmed") => Ok(CommitmentConfig::confirmed()),
+        Ok("processed") => Ok(CommitmentConfig::processed()),
+        Ok("finalized") => Ok(CommitmentConfig::finalized()),
+        Ok(other) => Err(format!("Invalid COMMITMENT {}: expected processed, confirmed or finalized", other).into()),
+    }
+}
+
+/// The Solana cluster swaps run against, picked with `CLUSTER`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Cluster {
+    MainnetBeta,
+    Devnet,
+}
+
+impl Cluster {
+    /// The public RPC endpoint used when `RPC_URL` is unset
+    fn default_rpc_url(self) -> &'static str {
+        match self {
+            Cluster::MainnetBeta => "https://api.mainnet-beta.solana.com",
+            Cluster::Devnet => "https://api.devnet.solana.com",
+        }
+    }
+}
+
+/// `CLUSTER`, `mainnet` (the default) or `devnet`. Only picks the default
+/// `RPC_URL`; an explicit `RPC_URL` must point at the same cluster. Jupiter
+/// and the `mints` constants are mainnet only, so on devnet the RPC NIFs
+/// work but swaps are refused, see `check_swap_cluster`.
+fn cluster() -> Result<Cluster, JupSwapError> {
+    match std::env::var("CLUSTER").as_deref() {
+        Err(_) | Ok("mainnet") | Ok("mainnet-beta") => Ok(Cluster::MainnetBeta),
+        Ok("devnet") => Ok(Cluster::Devnet),
+        Ok(other) => Err(format!("Invalid CLUSTER {}: expected mainnet or devnet", other).into()),
+    }
+}
+
+/// Refuses to build a swap off mainnet, where Jupiter's routes could never
+/// land
+fn check_swap_cluster() -> Result<(), JupSwapError> {
+    match cluster()? {
+        Cluster::MainnetBeta => Ok(()),
+        Cluster::Devnet => Err(JupSwapError::UnsupportedCluster(
+            "Jupiter only quotes mainnet routes, so swaps cannot run with CLUSTER=devnet".to_string(),
+        )),
+    }
+}
+
+fn rpc_url() -> Result<String, JupSwapError> {
+    let rpc_url = match std::env::var("RPC_URL") {
+        Ok(rpc_url) => rpc_url,
+        Err(_) => cluster()?.default_rpc_url().to_string(),
+    };
+
+    match reqwest::Url::parse(&rpc_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(rpc_url),
+        _ => Err(format!("Invalid RPC_URL: {}", rpc_url).into()),
+    }
+}
+
+/// `USE_SWAP_INSTRUCTIONS=true` assembles the transaction from
+/// `/swap-instructions` instead of signing the prebuilt `/swap` transaction
+fn use_swap_instructions() -> bool {
+    std::env::var("USE_SWAP_INSTRUCTIONS").map(|s| s == "true").unwrap_or(false)
+}
+
+/// Falling back to `/swap` after a structural failure is on unless
+/// `SWAP_INSTRUCTIONS_FALLBACK=false`
+fn swap_instructions_fallback() -> bool {
+    std::env::var("SWAP_INSTRUCTIONS_FALLBACK").map(|s| s != "false").unwrap_or(true)
+}
+
+/// `COMPUTE_UNIT_PRICE=estimated` prices the swap at Jupiter's estimated CU
+/// price instead of the one in its compute budget instructions
+fn use_estimated_compute_unit_price() -> bool {
+    std::env::var("COMPUTE_UNIT_PRICE").map(|s| s == "estimated").unwrap_or(false)
+}
+
+/// `AS_LEGACY_TRANSACTION=true` quotes routes that fit a legacy transaction
+/// and builds the swap as one, for signers without v0 support
+fn as_legacy_transaction() -> bool {
+    std::env::var("AS_LEGACY_TRANSACTION").map(|s| s == "true").unwrap_or(false)
+}
+
+/// `SKIP_EXISTING_SETUP=true` checks which ATAs already exist and leaves out
+/// their creation on the instruction path
+fn skip_existing_setup() -> bool {
+    std::env::var("SKIP_EXISTING_SETUP").map(|s| s == "true").unwrap_or(false)
+}
+
+fn strict_mode() -> bool {
+    std::env::var("STRICT_MODE").map(|s| s == "true").unwrap_or(false)
 }
 
-fn load(env: Env, _term: Term) -> bool {
+fn load(_env: Env, _term: Term) -> bool {
+    // Tracing events fall through to `log` records without a subscriber;
+    // `RUST_LOG` sets the level, `info` by default
+    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).try_init();
     let _ = get_runtime();
     true
 }
@@ -0,0 +1,22 @@
+//! Well-known mainnet mints, so callers do not have to paste addresses
+
+use solana_sdk::{pubkey, pubkey::Pubkey};
+
+/// Wrapped SOL, the mint Jupiter routes SOL through
+pub const SOL: Pubkey = pubkey!("So11111111111111111111111111111111111111112");
+pub const USDC: Pubkey = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+pub const USDT: Pubkey = pubkey!("Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB");
+pub const JUP: Pubkey = pubkey!("JUPyiwrYJFskUPiHa7hkeR8VUtAeFoSYbKedZNsDvCN");
+pub const BONK: Pubkey = pubkey!("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
+
+/// Looks up a mint by symbol, ignoring case. `WSOL` is an alias of `SOL`.
+pub fn by_symbol(symbol: &str) -> Option<Pubkey> {
+    match symbol.to_ascii_uppercase().as_str() {
+        "SOL" | "WSOL" => Some(SOL),
+        "USDC" => Some(USDC),
+        "USDT" => Some(USDT),
+        "JUP" => Some(JUP),
+        "BONK" => Some(BONK),
+        _ => None,
+    }
+}
@@ -0,0 +1,65 @@
+use {
+    solana_sdk::pubkey::Pubkey,
+    std::sync::{Arc, RwLock},
+};
+
+/// What a slippage strategy gets to decide on, known before quoting
+pub struct SlippageContext {
+    pub input_mint: Pubkey,
+    pub output_mint: Pubkey,
+    /// Input amount in atomic units
+    pub amount: u64,
+}
+
+/// Picks the slippage tolerance for a swap
+pub trait SlippageStrategy: Send + Sync {
+    /// Slippage in bps, or `None` to let Jupiter pick it
+    fn slippage_bps(&self, context: &SlippageContext) -> Option<u64>;
+}
+
+/// Always the same tolerance
+pub struct Fixed(pub u64);
+
+impl SlippageStrategy for Fixed {
+    fn slippage_bps(&self, _context: &SlippageContext) -> Option<u64> {
+        Some(self.0)
+    }
+}
+
+/// Jupiter's own default tolerance
+pub struct Auto;
+
+impl SlippageStrategy for Auto {
+    fn slippage_bps(&self, _context: &SlippageContext) -> Option<u64> {
+        None
+    }
+}
+
+const DEFAULT_SLIPPAGE_BPS: u64 = 20;
+
+static REGISTERED: RwLock<Option<Arc<dyn SlippageStrategy>>> = RwLock::new(None);
+
+/// Replaces the strategy configured through the environment
+pub fn register(strategy: Arc<dyn SlippageStrategy>) {
+    *REGISTERED.write().unwrap() = Some(strategy);
+}
+
+/// The registered strategy or, failing that, the one configured by
+/// `SLIPPAGE_MODE=auto` or `SLIPPAGE_BPS` (default 20 bps)
+pub fn current() -> Result<Arc<dyn SlippageStrategy>, String> {
+    if let Some(strategy) = REGISTERED.read().unwrap().as_ref() {
+        return Ok(strategy.clone());
+    }
+
+    if std::env::var("SLIPPAGE_MODE").map(|s| s == "auto").unwrap_or(false) {
+        return Ok(Arc::new(Auto));
+    }
+
+    match std::env::var("SLIPPAGE_BPS") {
+        Ok(s) => s
+            .parse::<u64>()
+            .map(|bps| Arc::new(Fixed(bps)) as Arc<dyn SlippageStrategy>)
+            .map_err(|e| format!("Invalid SLIPPAGE_BPS {}: {}", s, e)),
+        Err(_) => Ok(Arc::new(Fixed(DEFAULT_SLIPPAGE_BPS))),
+    }
+}
@@ -0,0 +1,104 @@
+use std::time::Duration;
+
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey, signature::Signature};
+use solana_transaction_status::{
+    option_serializer::OptionSerializer, EncodedConfirmedTransactionWithStatusMeta, UiInstruction,
+    UiTransactionEncoding,
+};
+
+/// Jupiter aggregator v6, which emits a `SwapEvent` for every leg it routes
+const JUPITER_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4");
+/// Prefix of an Anchor event emitted through a self-CPI: Anchor's
+/// `EVENT_IX_TAG` u64 (`sha256("anchor:event")[..8]` read big-endian,
+/// `0x1d9acb512ea545e4`) serialized with `to_le_bytes()`
+const EVENT_IX_TAG: [u8; 8] = [0xe4, 0x45, 0xa5, 0x2e, 0x51, 0xcb, 0x9a, 0x1d];
+/// `sha256("event:SwapEvent")[..8]`
+const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [0x40, 0xc6, 0xcd, 0xe8, 0x26, 0x08, 0x71, 0xe2];
+/// Borsh `SwapEvent { amm, input_mint, input_amount, output_mint, output_amount }`
+const SWAP_EVENT_LEN: usize = 32 + 32 + 8 + 32 + 8;
+
+/// A just-confirmed transaction may not be served by `getTransaction` yet
+const FETCH_ATTEMPTS: u32 = 3;
+const FETCH_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The output the swap actually paid out, summed over Jupiter's `SwapEvent`s
+/// into `output_mint`. `None` when the transaction or its events could not be
+/// read, in which case only the quoted amount is known.
+pub async fn filled_out_amount(rpc_client: &RpcClient, signature: &Signature, output_mint: &Pubkey) -> Option<u64> {
+    // `getTransaction` rejects `processed`, which `COMMITMENT` allows
+    let commitment = match rpc_client.commitment() {
+        commitment if commitment.is_at_least_confirmed() => commitment,
+        _ => CommitmentConfig::confirmed(),
+    };
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(commitment),
+        max_supported_transaction_version: Some(0),
+    };
+
+    let mut attempt = 1;
+    let transaction = loop {
+        match rpc_client.get_transaction_with_config(signature, config).await {
+            Ok(transaction) => break transaction,
+            Err(e) if attempt >= FETCH_ATTEMPTS => {
+                tracing::warn!(%signature, error = %e, "could not fetch the transaction to read the filled amount");
+                return None;
+            }
+            Err(_) => {
+                tokio::time::sleep(FETCH_RETRY_INTERVAL).await;
+                attempt += 1;
+            }
+        }
+    };
+
+    swap_output(&transaction, output_mint)
+}
+
+/// Sums the `output_amount` of the `SwapEvent`s into `output_mint` found in
+/// the transaction's inner instructions
+pub(crate) fn swap_output(transaction: &EncodedConfirmedTransactionWithStatusMeta, output_mint: &Pubkey) -> Option<u64> {
+    let meta = transaction.transaction.meta.as_ref()?;
+    let versioned = transaction.transaction.transaction.decode()?;
+
+    // Inner instructions index into the static keys followed by the keys
+    // loaded from lookup tables, writable first
+    let mut account_keys = versioned.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            account_keys.push(key.parse().ok()?);
+        }
+    }
+
+    let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions else {
+        return None;
+    };
+
+    let mut filled = None;
+    for instruction in inner_instructions.iter().flat_map(|inner| &inner.instructions) {
+        let UiInstruction::Compiled(instruction) = instruction else {
+            continue;
+        };
+        if account_keys.get(usize::from(instruction.program_id_index)) != Some(&JUPITER_PROGRAM_ID) {
+            continue;
+        }
+        let Ok(data) = solana_sdk::bs58::decode(&instruction.data).into_vec() else {
+            continue;
+        };
+        let Some(event) = data
+            .strip_prefix(&EVENT_IX_TAG)
+            .and_then(|data| data.strip_prefix(&SWAP_EVENT_DISCRIMINATOR))
+            .filter(|event| event.len() >= SWAP_EVENT_LEN)
+        else {
+            continue;
+        };
+
+        let event_output_mint = Pubkey::try_from(&event[72..104]).ok()?;
+        if event_output_mint == *output_mint {
+            let output_amount = u64::from_le_bytes(event[104..112].try_into().ok()?);
+            filled = Some(filled.unwrap_or(0u64).saturating_add(output_amount));
+        }
+    }
+
+    filled
+}
@@ -0,0 +1,28 @@
+//! Known ATAs for the derivation `derive_ata` and `derive_ata_with_program`
+//! use. The NIF crate only links inside the BEAM, so this checks the
+//! underlying `spl_associated_token_account` call directly.
+
+use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+
+const OWNER: Pubkey = solana_sdk::pubkey!("9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM");
+const USDC: Pubkey = solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+/// PayPal USD, a Token-2022 mint
+const PYUSD: Pubkey = solana_sdk::pubkey!("2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo");
+const TOKEN_2022_PROGRAM_ID: Pubkey = solana_sdk::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+#[test]
+fn token_program_ata() {
+    assert_eq!(
+        get_associated_token_address_with_program_id(&OWNER, &USDC, &spl_token::id()),
+        solana_sdk::pubkey!("FGETo8T8wMcN2wCjav8VK6eh3dLk63evNDPxzLSJra8B"),
+    );
+}
+
+#[test]
+fn token_2022_program_ata() {
+    assert_eq!(
+        get_associated_token_address_with_program_id(&OWNER, &PYUSD, &TOKEN_2022_PROGRAM_ID),
+        solana_sdk::pubkey!("897krAvWH3RbymaCYE3o9emopUwocieHuKTUk9nySpq6"),
+    );
+}
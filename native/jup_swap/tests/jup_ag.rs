@@ -0,0 +1,136 @@
+//! Fixture tests for the `jup_ag` client. The NIF crate only links inside the
+//! BEAM, so the rustler-free client module is compiled into this test on its
+//! own.
+
+#[path = "../src"]
+mod client {
+    #[allow(dead_code)]
+    pub mod jup_ag;
+}
+
+use client::jup_ag::{self, Error};
+
+fn response(status: u16, body: &'static str) -> reqwest::Response {
+    http::Response::builder().status(status).body(body).unwrap().into()
+}
+
+#[tokio::test]
+async fn empty_quote_data_is_no_route() {
+    let quote = jup_ag::quote_from_response(response(200, include_str!("fixtures/quote_empty_data.json"))).await;
+
+    assert!(matches!(quote, Err(Error::NoRoute)), "{quote:?}");
+}
+
+#[tokio::test]
+async fn quote_404_is_no_route() {
+    let quote = jup_ag::quote_from_response(response(404, include_str!("fixtures/quote_not_found.json"))).await;
+
+    assert!(matches!(quote, Err(Error::NoRoute)), "{quote:?}");
+}
+
+fn api_error(fixture: &str) -> Error {
+    let value = serde_json::from_str(fixture).unwrap();
+    jup_ag::maybe_jupiter_api_error::<jup_ag::Quote>(value).unwrap_err()
+}
+
+#[test]
+fn route_error_codes_are_no_route() {
+    for fixture in [
+        include_str!("fixtures/error_could_not_find_any_route.json"),
+        include_str!("fixtures/error_no_routes_found.json"),
+    ] {
+        let error = api_error(fixture);
+        assert!(matches!(error, Error::NoRoute), "{error:?}");
+    }
+}
+
+#[test]
+fn rate_limited_error_code_is_rate_limited() {
+    let error = api_error(include_str!("fixtures/error_rate_limited.json"));
+
+    assert!(matches!(error, Error::RateLimited { retry_after: None }), "{error:?}");
+}
+
+#[test]
+fn stale_quote_error_codes_are_quote_expired() {
+    for fixture in [
+        include_str!("fixtures/error_quote_expired.json"),
+        include_str!("fixtures/error_stale_quote.json"),
+    ] {
+        let value = serde_json::from_str(fixture).unwrap();
+        let error = jup_ag::maybe_jupiter_api_error::<jup_ag::SwapInstructions>(value).unwrap_err();
+        assert!(matches!(&error, Error::QuoteExpired(message) if !message.is_empty()), "{error:?}");
+    }
+}
+
+#[test]
+fn other_error_codes_keep_code_and_message() {
+    for (fixture, expected_code) in [
+        (include_str!("fixtures/error_token_not_tradable.json"), "TOKEN_NOT_TRADABLE"),
+        (include_str!("fixtures/error_circular_arbitrage.json"), "CIRCULAR_ARBITRAGE_IS_DISABLED"),
+    ] {
+        match api_error(fixture) {
+            Error::JupiterApi { code, message } => {
+                assert_eq!(code.as_deref(), Some(expected_code));
+                assert!(!message.is_empty());
+            }
+            error => panic!("expected a Jupiter API error, got {error:?}"),
+        }
+    }
+}
+
+fn legacy_quote_url(quote_config: jup_ag::QuoteConfig) -> String {
+    jup_ag::legacy_quote_url(
+        solana_sdk::pubkey::Pubkey::new_unique(),
+        solana_sdk::pubkey::Pubkey::new_unique(),
+        1_000,
+        false,
+        Some(0.5),
+        Some(10.0),
+        jup_ag::SwapMode::ExactIn,
+        quote_config,
+    )
+}
+
+/// The decoded value of `key` in the query of `url`
+fn query_param(url: &str, key: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).unwrap();
+    let value = url.query_pairs().find(|(k, _)| k == key).map(|(_, value)| value.into_owned());
+    value
+}
+
+#[test]
+fn quote_url_carries_dex_filters() {
+    let url = legacy_quote_url(jup_ag::QuoteConfig {
+        dexes: Some(vec!["Orca V2".to_string(), "Raydium".to_string()]),
+        ..Default::default()
+    });
+    assert_eq!(query_param(&url, "dexes").as_deref(), Some("Orca V2,Raydium"), "{url}");
+    assert!(!url.contains(' '), "{url}");
+
+    let url = legacy_quote_url(jup_ag::QuoteConfig {
+        exclude_dexes: Some(vec!["Lifinity V2".to_string()]),
+        ..Default::default()
+    });
+    assert_eq!(query_param(&url, "excludeDexes").as_deref(), Some("Lifinity V2"), "{url}");
+}
+
+#[test]
+fn quote_url_encodes_extra_params() {
+    let url = legacy_quote_url(jup_ag::QuoteConfig {
+        extra_params: vec![("preferDexes".to_string(), "A&B=C".to_string())],
+        ..Default::default()
+    });
+
+    assert_eq!(query_param(&url, "preferDexes").as_deref(), Some("A&B=C"), "{url}");
+    assert_eq!(query_param(&url, "B"), None, "{url}");
+}
+
+#[test]
+fn quote_url_has_no_empty_params() {
+    let url = legacy_quote_url(jup_ag::QuoteConfig::default());
+
+    assert_eq!(query_param(&url, "slippage").as_deref(), Some("0.5"), "{url}");
+    assert_eq!(query_param(&url, "feesBps").as_deref(), Some("10"), "{url}");
+    assert!(!url.contains("&&"), "{url}");
+}
@@ -0,0 +1,44 @@
+//! Fixture tests for reading Jupiter's `SwapEvent`s out of a fetched
+//! transaction. The NIF crate only links inside the BEAM, so the module is
+//! compiled into this test on its own.
+
+#[path = "../src"]
+mod client {
+    #[allow(dead_code)]
+    pub mod swap_event;
+}
+
+use client::swap_event;
+use solana_sdk::pubkey;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+/// A Jupiter v6 route BONK → SOL → USDC, the last hop split over two pools,
+/// in `getTransaction`'s base64 encoding. The transaction is constructed
+/// rather than captured (the pools and signature are placeholders) but its
+/// events use the on-chain layout. Some of the AMMs the route invokes
+/// are loaded from a lookup table, so the event CPIs only resolve to Jupiter
+/// when the loaded addresses are appended to the static keys.
+fn transaction() -> EncodedConfirmedTransactionWithStatusMeta {
+    serde_json::from_str(include_str!("fixtures/swap_event_transaction.json")).unwrap()
+}
+
+#[test]
+fn sums_the_swap_events_into_the_output_mint() {
+    let usdc = pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
+
+    assert_eq!(swap_event::swap_output(&transaction(), &usdc), Some(600_000 + 400_123));
+}
+
+#[test]
+fn reads_an_intermediate_leg() {
+    let sol = pubkey!("So11111111111111111111111111111111111111112");
+
+    assert_eq!(swap_event::swap_output(&transaction(), &sol), Some(7_000_000));
+}
+
+#[test]
+fn no_event_into_the_mint_is_none() {
+    let bonk = pubkey!("DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263");
+
+    assert_eq!(swap_event::swap_output(&transaction(), &bonk), None);
+}
@@ -0,0 +1,144 @@
+defmodule JupSwap.NativeTest do
+  use ExUnit.Case
+
+  @owner "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM"
+  @usdc "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+  @token_program "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+  @token_2022_program "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"
+  @native_sol "11111111111111111111111111111111"
+  @wrapped_sol "So11111111111111111111111111111111111111112"
+
+  describe "derive_ata_with_program/3" do
+    test "derives the Token program ATA" do
+      assert JupSwap.Native.derive_ata_with_program(@owner, @usdc, @token_program) ==
+               {:ok, "FGETo8T8wMcN2wCjav8VK6eh3dLk63evNDPxzLSJra8B"}
+    end
+
+    test "derives the Token-2022 program ATA" do
+      assert JupSwap.Native.derive_ata_with_program(@owner, @usdc, @token_2022_program) ==
+               {:ok, "GdjpegrtGwU3pgtzPivYVViSA8rmGL248qBVKzsrU3DD"}
+    end
+
+    test "rejects an invalid owner" do
+      assert {:error, _} = JupSwap.Native.derive_ata_with_program("not-a-key", @usdc, @token_program)
+    end
+  end
+
+  describe "UI amount conversion with explicit decimals" do
+    test "converts between UI and atomic amounts without a lookup" do
+      assert JupSwap.Native.to_atomic_amount(@usdc, 1.5, 6) == {:ok, 1_500_000}
+      assert JupSwap.Native.to_ui_amount(@usdc, 1_500_000, 6) == {:ok, 1.5}
+    end
+
+    test "rejects decimals above 18" do
+      assert {:error, _} = JupSwap.Native.to_atomic_amount(@usdc, 1.0, 19)
+    end
+  end
+
+  describe "known_mint/1" do
+    test "resolves symbols case-insensitively" do
+      assert JupSwap.Native.known_mint("USDC") == {:ok, @usdc}
+      assert JupSwap.Native.known_mint("sol") == {:ok, @wrapped_sol}
+    end
+
+    test "rejects an unknown symbol" do
+      assert {:error, _} = JupSwap.Native.known_mint("NOPE")
+    end
+  end
+
+  describe "normalize_sol_mint/2" do
+    test "maps native SOL to the wrapped mint when wrapping" do
+      assert JupSwap.Native.normalize_sol_mint(@native_sol, true) == {:ok, @wrapped_sol}
+    end
+
+    test "rejects native SOL without wrapping" do
+      assert {:error, _} = JupSwap.Native.normalize_sol_mint(@native_sol, false)
+    end
+
+    test "leaves other mints alone" do
+      assert JupSwap.Native.normalize_sol_mint(@wrapped_sol, false) == {:ok, @wrapped_sol}
+      assert JupSwap.Native.normalize_sol_mint(@usdc, true) == {:ok, @usdc}
+    end
+  end
+
+  describe "quick_swap/3" do
+    test "rejects an invalid mint without panicking" do
+      assert {:rejected, %{operation_id: _, reason: {:swap, reason}}} =
+               JupSwap.Native.quick_swap("not-a-mint", @usdc, 1_000)
+
+      assert reason =~ "output mint"
+    end
+
+    test "rejects a zero amount before quoting" do
+      assert {:rejected, %{reason: {:invalid_amount, "amount must be > 0"}}} =
+               JupSwap.Native.quick_swap(@usdc, @wrapped_sol, 0)
+    end
+  end
+
+  describe "quick_swap_with_key/4" do
+    test "rejects a malformed secret key" do
+      assert {:rejected, %{operation_id: _, reason: {:invalid_keypair, reason}}} =
+               JupSwap.Native.quick_swap_with_key(@usdc, @wrapped_sol, 1_000, "[1, 2")
+
+      assert reason =~ "private key"
+    end
+  end
+
+  describe "quick_swap_with_keyfile/4" do
+    test "reports a missing keypair file" do
+      path = Path.join(System.tmp_dir!(), "jup_swap_missing_keypair.json")
+
+      assert {:rejected, %{operation_id: _, reason: {:keypair_file, ^path, _}}} =
+               JupSwap.Native.quick_swap_with_keyfile(@usdc, @wrapped_sol, 1_000, path)
+    end
+  end
+
+  describe "build_swap_transaction/4" do
+    test "rejects an invalid owner" do
+      assert {:error, {:swap, reason}} = JupSwap.Native.build_swap_transaction(@usdc, @wrapped_sol, 1_000, "not-a-key")
+      assert reason =~ "owner"
+    end
+  end
+
+  describe "best_quote/3" do
+    test "rejects an invalid mint without quoting" do
+      assert {:error, {:swap, reason}} = JupSwap.Native.best_quote("not-a-mint", @usdc, 1_000)
+      assert reason =~ "input mint"
+    end
+  end
+
+  describe "quick_swap_async/3" do
+    test "messages the caller with the outcome" do
+      reference = JupSwap.Native.quick_swap_async("not-a-mint", @usdc, 1_000)
+
+      assert_receive {:jup_swap_result, ^reference, {:rejected, %{reason: {:swap, reason}}}}, 1_000
+      assert reason =~ "output mint"
+    end
+  end
+
+  describe "drain/1 and resume/0" do
+    test "refuses swaps until resumed" do
+      on_exit(fn -> JupSwap.Native.resume() end)
+
+      assert JupSwap.Native.drain(0) == []
+
+      assert {:rejected, %{reason: :draining}} =
+               JupSwap.Native.quick_swap_with_key(@usdc, @wrapped_sol, 1_000, "[1, 2")
+
+      assert JupSwap.Native.resume() == :ok
+
+      # Past the draining check, the malformed key is what fails now
+      assert {:rejected, %{reason: {:invalid_keypair, _}}} =
+               JupSwap.Native.quick_swap_with_key(@usdc, @wrapped_sol, 1_000, "[1, 2")
+    end
+  end
+
+  describe "swap_from_quote/2" do
+    test "rejects a malformed quote without swapping" do
+      assert {:rejected, %{operation_id: _, reason: {:swap, reason}}} =
+               JupSwap.Native.swap_from_quote("{}", "SOLANA_PRIVATE_KEY")
+
+      assert reason =~ "Invalid quote"
+    end
+  end
+end